@@ -0,0 +1,97 @@
+//! Benchmarks for the hot loops of the renderer, run them with `cargo bench`.
+//!
+//! The baseline numbers next to each benchmark are criterion medians from a
+//! single core of an Intel Xeon VM with the default config. They only say
+//! something relative to a run on the same machine.
+//!
+//! Writing whole pixels instead of four separate bytes didn't measurably speed
+//! up a full 3840x2160 frame with 25% of the cells visited: the median was
+//! 14.3 ms before and 14.3 ms after, with identical output.
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+
+use walk_bg::{Config, Grid, draw};
+
+const SIZES: [(&str, u32, u32); 2] = [("1080p", 1920, 1080), ("4K", 3840, 2160)];
+
+/// Visit roughly `density` of all cells in a fixed pattern
+fn grid_with_density(config: &Config, width: u32, height: u32, density: f32) -> Grid {
+    let (grid_width, grid_height) = config.grid_size(width, height);
+    let mut grid = Grid::new(grid_width, grid_height);
+    let threshold = (density * 100.0) as u32;
+    for y in 0..grid_height {
+        for x in 0..grid_width {
+            if (x * 37 + y * 61) % 100 < threshold {
+                grid.visit(x, y);
+            }
+        }
+    }
+    grid
+}
+
+/// Baseline: 468 µs at 1080p, 4.27 ms at 4K
+fn background_fill(c: &mut Criterion) {
+    let mut group = c.benchmark_group("background_fill");
+    for (name, width, height) in SIZES {
+        let mut buffer = vec![0u8; (width * height * 4) as usize];
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                draw::fill_background(&mut buffer, width, height, black_box([26, 26, 26, 255]))
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Without visits no connections are drawn, so 0% measures the dot loop alone.
+///
+/// Baseline at 1080p: 1.64 ms at 0%, 1.68 ms at 25%, 4.58 ms at 100%.
+/// Baseline at 4K: 7.78 ms at 0%, 8.63 ms at 25%, 19.2 ms at 100%.
+fn dot_grid(c: &mut Criterion) {
+    let config = Config::default();
+    let mut group = c.benchmark_group("draw_dot_grid");
+    for (name, width, height) in SIZES {
+        let mut buffer = vec![0u8; (width * height * 4) as usize];
+        for density in [0.0, 0.25, 1.0] {
+            let grid = grid_with_density(&config, width, height, density);
+            let id = BenchmarkId::new(name, format!("{}%", (density * 100.0) as u32));
+            group.bench_function(id, |b| {
+                b.iter(|| {
+                    draw::draw_dot_grid(&mut buffer, width, height, &config, &grid, &[(5, 5)], &[])
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+/// Baseline: 7.7 µs for the horizontal and 7.4 µs for the diagonal line
+fn draw_line(c: &mut Criterion) {
+    let (width, height) = (1920, 1080);
+    let mut buffer = vec![0u8; (width * height * 4) as usize];
+    let color = [96, 96, 96, 255];
+    let mut group = c.benchmark_group("draw_line");
+    group.bench_function("horizontal", |b| {
+        b.iter(|| {
+            draw::draw_line(
+                &mut buffer,
+                width,
+                height,
+                0,
+                540,
+                1919,
+                540,
+                &color,
+                &color,
+            )
+        })
+    });
+    group.bench_function("diagonal", |b| {
+        b.iter(|| draw::draw_line(&mut buffer, width, height, 0, 0, 1919, 1079, &color, &color))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, background_fill, dot_grid, draw_line);
+criterion_main!(benches);
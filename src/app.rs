@@ -0,0 +1,639 @@
+use smithay_client_toolkit::{
+    compositor::{CompositorHandler, CompositorState, Region},
+    delegate_compositor, delegate_layer, delegate_output, delegate_pointer, delegate_registry,
+    delegate_seat, delegate_shm,
+    output::{OutputHandler, OutputState},
+    registry::{ProvidesRegistryState, RegistryState},
+    registry_handlers,
+    seat::{
+        Capability, SeatHandler, SeatState,
+        pointer::{PointerEvent, PointerEventKind, PointerHandler},
+    },
+    shell::WaylandSurface,
+    shell::wlr_layer::{self, LayerShellHandler, LayerSurface, LayerSurfaceConfigure},
+    shm::{Shm, ShmHandler},
+};
+use walk_bg::{
+    types::{Config, Grid},
+    utils::{Rng, WalkAlgorithm},
+};
+use wayland_client::{
+    Connection, Proxy, QueueHandle, globals,
+    protocol::{wl_output, wl_pointer, wl_seat, wl_shm_pool, wl_surface},
+};
+
+use crate::error::WalkBgError;
+use crate::surface::{OutputInfo, OutputSurface, place, shell_layer};
+
+/// Stores application state
+pub struct App {
+    config: Config,
+    registry_state: RegistryState,
+    output_state: OutputState,
+    seat_state: SeatState,
+    compositor_state: CompositorState,
+    shm_state: Shm,
+    layer_shell: Option<wlr_layer::LayerShell>,
+    /// One background per output. The first one is the primary surface, its
+    /// walk is saved and reported.
+    surfaces: Vec<OutputSurface>,
+    walk: WalkAlgorithm,
+    rng: Rng,
+    pointer: Option<wl_pointer::WlPointer>,
+    total_steps: u64,
+    started: std::time::Instant,
+    /// Set once the last background was closed and couldn't be recreated
+    should_exit: bool,
+}
+
+impl App {
+    pub fn new(
+        global_list: &globals::GlobalList,
+        qh: &QueueHandle<Self>,
+    ) -> Result<Self, WalkBgError> {
+        Ok(Self {
+            config: Config::default(),
+            registry_state: RegistryState::new(global_list),
+            output_state: OutputState::new(global_list, qh),
+            seat_state: SeatState::new(global_list, qh),
+            compositor_state: CompositorState::bind(global_list, qh)
+                .map_err(WalkBgError::Compositor)?,
+            shm_state: Shm::bind(global_list, qh).map_err(WalkBgError::Shm)?,
+            layer_shell: None,
+            surfaces: Vec::new(),
+            walk: WalkAlgorithm::Uniform,
+            rng: Rng::new(),
+            pointer: None,
+            total_steps: 0,
+            started: std::time::Instant::now(),
+            should_exit: false,
+        })
+    }
+
+    /// Create the backgrounds: one on the configured `output`, or one on every
+    /// output if none is configured. Outputs that are connected later get
+    /// their own background in [`OutputHandler::new_output`].
+    pub fn create_surface(
+        &mut self,
+        qh: &QueueHandle<Self>,
+        globals: &globals::GlobalList,
+    ) -> Result<(), WalkBgError> {
+        self.layer_shell =
+            Some(wlr_layer::LayerShell::bind(globals, qh).map_err(WalkBgError::LayerShell)?);
+        self.add_surfaces(qh)
+    }
+
+    /// Create the backgrounds for the configured `output`, or for every output
+    fn add_surfaces(&mut self, qh: &QueueHandle<Self>) -> Result<(), WalkBgError> {
+        if let Some(key) = self.config.get_output() {
+            let output = self.find_output(key);
+            self.add_surface(qh, output)?;
+        } else {
+            let outputs: Vec<_> = self.output_state.outputs().collect();
+            if outputs.is_empty() {
+                // Let the compositor choose until outputs show up
+                self.add_surface(qh, None)?;
+            }
+            for output in outputs {
+                self.add_surface(qh, Some(output))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a background on `output`, or on one the compositor chooses
+    fn add_surface(
+        &mut self,
+        qh: &QueueHandle<Self>,
+        output: Option<wl_output::WlOutput>,
+    ) -> Result<(), WalkBgError> {
+        let Some(layer_shell) = &self.layer_shell else {
+            return Ok(());
+        };
+        let surface = self.compositor_state.create_surface(qh);
+        let layer_surface = layer_shell.create_layer_surface(
+            qh,
+            surface,
+            shell_layer(self.config.get_layer()),
+            Some("walk_bg"),
+            output.as_ref(),
+        );
+
+        place(&layer_surface, &self.config);
+        layer_surface.set_keyboard_interactivity(wlr_layer::KeyboardInteractivity::None);
+
+        // An empty input region lets every click pass through to what is
+        // behind the background
+        if !self.config.interactive() {
+            match Region::new(&self.compositor_state) {
+                Ok(region) => layer_surface
+                    .wl_surface()
+                    .set_input_region(Some(region.wl_region())),
+                Err(e) => log::warn!("Failed to create empty input region: {e}"),
+            }
+        }
+        layer_surface.commit();
+
+        self.surfaces.push(OutputSurface::new(
+            layer_surface,
+            output,
+            self.config.clone(),
+        )?);
+        Ok(())
+    }
+
+    /// Find the output matching `key`, either by its name or, less
+    /// specific, by "Make Model"
+    fn find_output(&self, key: &str) -> Option<wl_output::WlOutput> {
+        let outputs: Vec<_> = self
+            .output_state
+            .outputs()
+            .filter_map(|output| Some((self.output_state.info(&output)?, output)))
+            .collect();
+        for (info, _) in &outputs {
+            log::info!(
+                "Found output {} ({} {})",
+                info.name.as_deref().unwrap_or("without name"),
+                info.make,
+                info.model
+            );
+        }
+
+        if let Some((_, output)) = outputs
+            .iter()
+            .find(|(info, _)| info.name.as_deref() == Some(key))
+        {
+            log::info!("Using output '{key}', matched by name");
+            return Some(output.clone());
+        }
+        if let Some((_, output)) = outputs
+            .iter()
+            .find(|(info, _)| format!("{} {}", info.make, info.model) == key)
+        {
+            log::info!("Using output '{key}', matched by make and model");
+            return Some(output.clone());
+        }
+
+        log::warn!("No output matches '{key}', letting the compositor choose");
+        None
+    }
+
+    pub fn set_config(&mut self, config: Config) {
+        self.apply_seed(&config);
+        self.walk =
+            WalkAlgorithm::from_name(config.get_walk_algorithm()).unwrap_or(WalkAlgorithm::Uniform);
+        self.config = config;
+    }
+
+    /// Restart the random numbers from the seed of `config` if it differs
+    /// from the current one
+    fn apply_seed(&mut self, config: &Config) {
+        if self.rng.reseed(self.config.get_seed(), config.get_seed()) {
+            log::info!("Seeding the walk with {}", config.get_seed().unwrap());
+        }
+    }
+
+    /// Apply a new config at runtime, resizing the grids if the spacing
+    /// changed.
+    ///
+    /// The random number generator is only re-seeded if `seed` changed, so
+    /// otherwise the walk continues without a break. A different `output`
+    /// starts over with new backgrounds.
+    pub fn reload_config(&mut self, config: Config, qh: &QueueHandle<Self>) {
+        let output_changed = config.get_output() != self.config.get_output();
+        self.set_config(config);
+
+        if output_changed && self.layer_shell.is_some() {
+            log::info!("The output changed, creating new backgrounds");
+            self.surfaces.clear();
+            if let Err(e) = self.add_surfaces(qh) {
+                log::error!("Failed to create the backgrounds: {e}");
+            }
+            return;
+        }
+        for surface in &mut self.surfaces {
+            surface.reload(self.config.clone());
+        }
+    }
+
+    pub fn get_config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Whether any background has been configured and can be drawn
+    pub fn is_configured(&self) -> bool {
+        self.surfaces.iter().any(OutputSurface::is_configured)
+    }
+
+    /// Whether every background is gone, so there is nothing left to draw on
+    pub fn should_exit(&self) -> bool {
+        self.should_exit
+    }
+
+    /// Whether any background still has a frame to show
+    pub fn is_drawing(&self) -> bool {
+        self.surfaces.iter().any(OutputSurface::is_drawing)
+    }
+
+    /// Position of the main walker on the primary output
+    pub fn get_current_pos(&self) -> Option<(u32, u32)> {
+        self.surfaces.first().map(OutputSurface::get_current_pos)
+    }
+
+    /// Positions of all walkers on the primary output, the main walker first
+    pub fn get_positions(&self) -> &[(u32, u32)] {
+        self.surfaces
+            .first()
+            .map_or(&[], OutputSurface::get_positions)
+    }
+
+    /// Grid of the primary output
+    pub fn get_grid(&self) -> Option<&Grid> {
+        self.surfaces.first().map(OutputSurface::get_grid)
+    }
+
+    /// Number of walk steps taken since the start
+    pub fn get_total_steps(&self) -> u64 {
+        self.total_steps
+    }
+
+    /// Time since the daemon started
+    pub fn uptime(&self) -> std::time::Duration {
+        self.started.elapsed()
+    }
+
+    /// Frames drawn on the primary output within the last second
+    pub fn fps(&self) -> f32 {
+        self.surfaces.first().map_or(0.0, OutputSurface::fps)
+    }
+
+    /// Save the walk on the primary output so the next start can continue it
+    pub fn save_state(&self) {
+        if let Some(surface) = self.surfaces.first() {
+            surface.save_state(&self.rng);
+        }
+    }
+
+    /// Write the frame shown on the primary output to a PNG at `path`
+    pub fn export_png(&self, path: &std::path::Path) -> Result<(), WalkBgError> {
+        self.surfaces
+            .first()
+            .ok_or(WalkBgError::NothingDrawn)?
+            .export_png(path)
+    }
+
+    /// Advance the walk on every configured output by one step
+    pub fn walk_step(&mut self) {
+        for surface in &mut self.surfaces {
+            if surface.is_configured() {
+                surface.walk_step(self.walk, &mut self.rng);
+            }
+        }
+        self.total_steps += 1;
+    }
+
+    /// Largest width and height among the current modes of all outputs
+    fn largest_output_size(&self) -> Option<(u32, u32)> {
+        self.output_state
+            .outputs()
+            .filter_map(|output| self.mode_size(&output))
+            .reduce(|(max_width, max_height), (width, height)| {
+                (max_width.max(width), max_height.max(height))
+            })
+    }
+
+    /// Size of the current mode of `output`, rotated like the output
+    fn mode_size(&self, output: &wl_output::WlOutput) -> Option<(u32, u32)> {
+        let info = self.output_state.info(output)?;
+        let mode = info.modes.iter().find(|mode| mode.current)?;
+        let (width, height) = (mode.dimensions.0 as u32, mode.dimensions.1 as u32);
+
+        // Modes are given before the output is rotated
+        match info.transform {
+            wl_output::Transform::_90
+            | wl_output::Transform::_270
+            | wl_output::Transform::Flipped90
+            | wl_output::Transform::Flipped270 => Some((height, width)),
+            _ => Some((width, height)),
+        }
+    }
+
+    /// Draw a new frame on every output
+    pub fn draw(&mut self, qh: &QueueHandle<Self>) {
+        for surface in &mut self.surfaces {
+            surface.draw(&self.shm_state, qh);
+        }
+    }
+
+    /// Present the frames rendered on worker threads that are done. Returns
+    /// `true` if requests were queued.
+    pub fn present_pending(&mut self, qh: &QueueHandle<Self>) -> bool {
+        let mut presented = false;
+        for surface in &mut self.surfaces {
+            presented |= surface.present_pending(&self.shm_state, qh);
+        }
+        presented
+    }
+
+    fn surface_mut(&mut self, wl_surface: &wl_surface::WlSurface) -> Option<&mut OutputSurface> {
+        let index = self.surface_index(wl_surface)?;
+        Some(&mut self.surfaces[index])
+    }
+
+    /// Position of the background drawn on `wl_surface` in `surfaces`
+    fn surface_index(&self, wl_surface: &wl_surface::WlSurface) -> Option<usize> {
+        self.surfaces
+            .iter()
+            .position(|surface| surface.wl_surface() == wl_surface)
+    }
+
+    /// Set up the surface at `index` for `size`, as asked for by a configure
+    fn configure_surface(&mut self, index: usize, size: (u32, u32)) {
+        // Fall back to the first output for a surface the compositor placed
+        let output_size = self.surfaces[index]
+            .current_output()
+            .cloned()
+            .or_else(|| self.output_state.outputs().next())
+            .and_then(|output| self.mode_size(&output));
+        let output = OutputInfo {
+            size: output_size,
+            largest: self.largest_output_size(),
+            has_pointer: self.pointer.is_some(),
+        };
+        self.surfaces[index].configure(size, output, index == 0, self.walk, &mut self.rng);
+    }
+}
+
+impl CompositorHandler for App {
+    fn scale_factor_changed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        surface: &wl_surface::WlSurface,
+        new_factor: i32,
+    ) {
+        let Some(index) = self.surface_index(surface) else {
+            return;
+        };
+
+        // Resize the buffer for the new scale, an unconfigured surface picks
+        // it up on its first configure
+        let output_surface = &mut self.surfaces[index];
+        if output_surface.set_scale(new_factor.max(1) as u32) && output_surface.is_configured() {
+            let size = output_surface.requested_size();
+            self.configure_surface(index, size);
+        }
+    }
+
+    fn transform_changed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        surface: &wl_surface::WlSurface,
+        new_transform: wl_output::Transform,
+    ) {
+        if let Some(surface) = self.surface_mut(surface) {
+            surface.set_transform(new_transform);
+        }
+    }
+
+    fn frame(
+        &mut self,
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+        surface: &wl_surface::WlSurface,
+        _time: u32,
+    ) {
+        // `surface_mut` would borrow `shm_state` as well
+        if let Some(index) = self.surface_index(surface) {
+            self.surfaces[index].frame_done(&self.shm_state, qh);
+        }
+    }
+
+    fn surface_enter(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        surface: &wl_surface::WlSurface,
+        output: &wl_output::WlOutput,
+    ) {
+        if let Some(surface) = self.surface_mut(surface) {
+            surface.enter(output);
+        }
+    }
+
+    fn surface_leave(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        surface: &wl_surface::WlSurface,
+        output: &wl_output::WlOutput,
+    ) {
+        if let Some(surface) = self.surface_mut(surface) {
+            surface.leave(output);
+        }
+    }
+}
+
+impl OutputHandler for App {
+    fn output_state(&mut self) -> &mut OutputState {
+        &mut self.output_state
+    }
+
+    fn new_output(
+        &mut self,
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+        output: wl_output::WlOutput,
+    ) {
+        // Outputs connected before the surfaces are created are handled there
+        if self.layer_shell.is_none() {
+            return;
+        }
+        let wanted = match self.config.get_output() {
+            // A configured output that wasn't connected yet replaces the
+            // background the compositor placed
+            Some(key) => {
+                self.surfaces
+                    .iter()
+                    .all(|surface| surface.output().is_none())
+                    && self.find_output(key).as_ref() == Some(&output)
+            }
+            None => !self
+                .surfaces
+                .iter()
+                .any(|surface| surface.output() == Some(&output)),
+        };
+        if !wanted {
+            return;
+        }
+
+        if self.config.get_output().is_some() {
+            self.surfaces.clear();
+        } else {
+            self.surfaces.retain(|surface| surface.output().is_some());
+        }
+        log::info!("Creating a background for a new output");
+        if let Err(e) = self.add_surface(qh, Some(output)) {
+            log::error!("Failed to create a background for the new output: {e}");
+        }
+    }
+
+    fn update_output(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _output: wl_output::WlOutput,
+    ) {
+    }
+
+    fn output_destroyed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        output: wl_output::WlOutput,
+    ) {
+        self.surfaces
+            .retain(|surface| surface.output() != Some(&output));
+    }
+}
+
+impl LayerShellHandler for App {
+    fn closed(&mut self, _conn: &Connection, qh: &QueueHandle<Self>, layer: &LayerSurface) {
+        let Some(index) = self.surface_index(layer.wl_surface()) else {
+            return;
+        };
+        // Nothing saves the walk of the primary surface once it's gone
+        if index == 0 {
+            self.surfaces[0].save_state(&self.rng);
+        }
+        let closed = self.surfaces.remove(index);
+
+        // A surface closed before its first configure was refused, recreating
+        // it would only be refused again
+        let output = closed
+            .output()
+            .filter(|output| self.output_state.outputs().any(|other| &other == *output))
+            .cloned();
+        match output {
+            Some(output) if closed.is_configured() => {
+                log::warn!("Layer surface closed, recreating it");
+                let count = self.surfaces.len();
+                match self.add_surface(qh, Some(output)) {
+                    // Put it back in place, so the primary surface stays first
+                    Ok(()) if self.surfaces.len() > count => {
+                        self.surfaces[index..].rotate_right(1);
+                    }
+                    Ok(()) => {}
+                    Err(e) => log::error!("Failed to recreate the layer surface: {e}"),
+                }
+            }
+            _ => log::warn!("Layer surface closed"),
+        }
+
+        if self.surfaces.is_empty() {
+            self.should_exit = true;
+        }
+    }
+
+    fn configure(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        layer: &LayerSurface,
+        configure: LayerSurfaceConfigure,
+        _serial: u32,
+    ) {
+        let Some(index) = self.surface_index(layer.wl_surface()) else {
+            return;
+        };
+        self.configure_surface(index, configure.new_size);
+    }
+}
+
+impl SeatHandler for App {
+    fn seat_state(&mut self) -> &mut SeatState {
+        &mut self.seat_state
+    }
+
+    fn new_seat(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _seat: wl_seat::WlSeat) {}
+
+    fn new_capability(
+        &mut self,
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+        seat: wl_seat::WlSeat,
+        capability: Capability,
+    ) {
+        if capability == Capability::Pointer && self.pointer.is_none() {
+            match self.seat_state.get_pointer(qh, &seat) {
+                Ok(pointer) => self.pointer = Some(pointer),
+                Err(e) => log::warn!("Failed to get pointer: {e}"),
+            }
+        }
+    }
+
+    fn remove_capability(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _seat: wl_seat::WlSeat,
+        capability: Capability,
+    ) {
+        if capability == Capability::Pointer
+            && let Some(pointer) = self.pointer.take()
+            && pointer.version() >= 3
+        {
+            pointer.release();
+        }
+    }
+
+    fn remove_seat(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _seat: wl_seat::WlSeat) {
+    }
+}
+
+impl PointerHandler for App {
+    fn pointer_frame(
+        &mut self,
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+        _pointer: &wl_pointer::WlPointer,
+        events: &[PointerEvent],
+    ) {
+        let event = events.iter().rfind(|event| {
+            matches!(
+                event.kind,
+                PointerEventKind::Enter { .. } | PointerEventKind::Motion { .. }
+            )
+        });
+        if let Some(event) = event
+            && let Some(index) = self.surface_index(&event.surface)
+        {
+            self.surfaces[index].start_at_cursor(event.position, &self.shm_state, qh);
+        }
+    }
+}
+
+impl ShmHandler for App {
+    fn shm_state(&mut self) -> &mut Shm {
+        &mut self.shm_state
+    }
+}
+
+impl ProvidesRegistryState for App {
+    fn registry(&mut self) -> &mut RegistryState {
+        &mut self.registry_state
+    }
+
+    registry_handlers![OutputState, SeatState];
+}
+
+delegate_compositor!(App);
+delegate_output!(App);
+delegate_shm!(App);
+delegate_layer!(App);
+delegate_registry!(App);
+delegate_seat!(App);
+delegate_pointer!(App);
+
+wayland_client::delegate_noop!(App: ignore wl_shm_pool::WlShmPool);
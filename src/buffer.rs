@@ -0,0 +1,276 @@
+//! Shared memory buffers that frames are drawn into
+
+use std::{
+    os::fd::AsFd,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use smithay_client_toolkit::shm::Shm;
+use wayland_client::{
+    Connection, Dispatch, QueueHandle,
+    protocol::{wl_buffer, wl_shm, wl_shm_pool, wl_surface},
+};
+
+use crate::app::App;
+
+/// Most buffers a surface cycles through. Two are enough unless the
+/// compositor holds on to the buffers it was given.
+const MAX_BUFFERS: usize = 3;
+
+/// A buffer in the shared memory file
+struct Buffer<C> {
+    wl_buffer: wl_buffer::WlBuffer,
+    offset: usize,
+    /// Whether the compositor may still read from the buffer
+    busy: Arc<AtomicBool>,
+    /// What the buffer shows, `None` if it doesn't hold a complete frame
+    contents: Option<C>,
+}
+
+/// Buffers in a shared memory file. A buffer is only handed out for drawing
+/// once the compositor released it, so it never reads a half drawn frame.
+///
+/// Each buffer remembers its contents as a `C`, so a frame can be drawn on top
+/// of the one the buffer already holds.
+pub struct ShmBuffers<C> {
+    file: std::fs::File,
+    mmap: Option<memmap2::MmapMut>,
+    pool: Option<wl_shm_pool::WlShmPool>,
+    /// Size of the file and the pool in bytes
+    capacity: usize,
+    /// Width, height and format of every buffer
+    layout: (u32, u32, wl_shm::Format),
+    buffers: Vec<Buffer<C>>,
+    /// Index of the buffer attached last
+    attached: Option<usize>,
+}
+
+impl<C> ShmBuffers<C> {
+    pub fn new() -> std::io::Result<Self> {
+        let file = tempfile::tempfile()?;
+        file.lock()?;
+
+        Ok(Self {
+            file,
+            mmap: None,
+            pool: None,
+            capacity: 0,
+            layout: (0, 0, wl_shm::Format::Argb8888),
+            buffers: Vec::new(),
+            attached: None,
+        })
+    }
+
+    /// Use buffers of the given size and format, buffers of another layout
+    /// are dropped
+    pub fn set_layout(&mut self, width: u32, height: u32, format: wl_shm::Format) {
+        if self.layout == (width, height, format) {
+            return;
+        }
+        self.layout = (width, height, format);
+        self.attached = None;
+        for buffer in self.buffers.drain(..) {
+            buffer.wl_buffer.destroy();
+        }
+    }
+
+    /// Forget what the buffers show, so the next frames are drawn completely
+    pub fn clear_contents(&mut self) {
+        for buffer in &mut self.buffers {
+            buffer.contents = None;
+        }
+    }
+
+    /// Size of a single buffer in bytes
+    fn buffer_size(&self) -> usize {
+        self.layout.0 as usize * 4 * self.layout.1 as usize
+    }
+
+    /// Find a buffer the compositor isn't reading from, or add one. `None` if
+    /// all buffers are in use.
+    pub fn acquire(&mut self, shm: &Shm, qh: &QueueHandle<App>) -> Option<usize> {
+        if let Some(index) = self.free_buffer() {
+            return Some(index);
+        }
+        if self.buffers.len() >= MAX_BUFFERS {
+            log::debug!("All buffers are in use, skipping the frame");
+            return None;
+        }
+
+        let size = self.buffer_size();
+        let offset = self.buffers.len() * size;
+        if let Err(e) = self.reserve(offset + size, shm, qh) {
+            log::error!("Failed to allocate a buffer: {e}");
+            return None;
+        }
+
+        let (width, height, format) = self.layout;
+        let busy = Arc::new(AtomicBool::new(false));
+        let wl_buffer = self.pool.as_ref()?.create_buffer(
+            offset as i32,
+            width as i32,
+            height as i32,
+            width as i32 * 4,
+            format,
+            qh,
+            busy.clone(),
+        );
+        self.buffers.push(Buffer {
+            wl_buffer,
+            offset,
+            busy,
+            contents: None,
+        });
+        Some(self.buffers.len() - 1)
+    }
+
+    /// Index of the first buffer the compositor isn't reading from
+    fn free_buffer(&self) -> Option<usize> {
+        self.buffers
+            .iter()
+            .position(|buffer| !buffer.busy.load(Ordering::Relaxed))
+    }
+
+    /// Grow the file, its mapping and the pool to hold at least `size` bytes.
+    ///
+    /// # Safety
+    /// We use unsafe for mapping a file mutably into memory. The underlying file is
+    /// locked by default and there should be no program that randomly writes to any
+    /// tempfile. If you have a suggestion on how to handle this safer, feel free to
+    /// open an issue.
+    fn reserve(&mut self, size: usize, shm: &Shm, qh: &QueueHandle<App>) -> std::io::Result<()> {
+        if size > i32::MAX as usize {
+            return Err(std::io::Error::other("buffers don't fit into a shm pool"));
+        }
+        if size > self.capacity {
+            self.file.set_len(size as u64)?;
+            self.capacity = size;
+            // The old mapping ends before the new part of the file
+            self.mmap = None;
+            if let Some(pool) = &self.pool {
+                pool.resize(size as i32);
+            } else {
+                let wl_shm = shm.wl_shm();
+                self.pool = Some(wl_shm.create_pool(self.file.as_fd(), size as i32, qh, ()));
+            }
+        }
+        if self.mmap.is_none() {
+            self.mmap = Some(unsafe { memmap2::MmapMut::map_mut(&self.file)? });
+        }
+        Ok(())
+    }
+
+    /// Pixels of the buffer at `index`
+    pub fn data(&mut self, index: usize) -> &mut [u8] {
+        let size = self.buffer_size();
+        let offset = self.buffers[index].offset;
+        &mut self.mmap.as_mut().unwrap()[offset..offset + size]
+    }
+
+    /// Pixels of the buffer attached last, `None` if none was attached since
+    /// the layout changed
+    pub fn attached_data(&self) -> Option<&[u8]> {
+        let size = self.buffer_size();
+        let offset = self.buffers[self.attached?].offset;
+        Some(&self.mmap.as_ref()?[offset..offset + size])
+    }
+
+    /// What the buffer at `index` shows, if it holds a complete frame
+    pub fn contents(&self, index: usize) -> Option<&C> {
+        self.buffers[index].contents.as_ref()
+    }
+
+    pub fn set_contents(&mut self, index: usize, contents: Option<C>) {
+        self.buffers[index].contents = contents;
+    }
+
+    /// Attach the buffer at `index` to `surface`. It stays in use until the
+    /// compositor releases it.
+    pub fn attach(&mut self, index: usize, surface: &wl_surface::WlSurface) {
+        self.attached = Some(index);
+        let buffer = &self.buffers[index];
+        buffer.busy.store(true, Ordering::Relaxed);
+        surface.attach(Some(&buffer.wl_buffer), 0, 0);
+    }
+}
+
+impl<C> Drop for ShmBuffers<C> {
+    fn drop(&mut self) {
+        for buffer in self.buffers.drain(..) {
+            buffer.wl_buffer.destroy();
+        }
+        if let Some(pool) = self.pool.take() {
+            pool.destroy();
+        }
+    }
+}
+
+impl Dispatch<wl_buffer::WlBuffer, Arc<AtomicBool>> for App {
+    fn event(
+        _state: &mut Self,
+        _buffer: &wl_buffer::WlBuffer,
+        event: wl_buffer::Event,
+        busy: &Arc<AtomicBool>,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let wl_buffer::Event::Release = event {
+            busy.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixStream;
+
+    use wayland_client::Proxy;
+
+    use super::*;
+
+    /// Add a buffer backed by an inert protocol object, so no compositor is needed
+    fn push_buffer(buffers: &mut ShmBuffers<()>, conn: &Connection) -> Arc<AtomicBool> {
+        let busy = Arc::new(AtomicBool::new(false));
+        buffers.buffers.push(Buffer {
+            wl_buffer: wl_buffer::WlBuffer::inert(conn.backend().downgrade()),
+            offset: 0,
+            busy: busy.clone(),
+            contents: Some(()),
+        });
+        busy
+    }
+
+    #[test]
+    fn buffers_are_busy_until_released() {
+        let (socket, _server) = UnixStream::pair().unwrap();
+        let conn = Connection::from_socket(socket).unwrap();
+        let surface = wl_surface::WlSurface::inert(conn.backend().downgrade());
+        let mut buffers = ShmBuffers::new().unwrap();
+        buffers.set_layout(4, 4, wl_shm::Format::Argb8888);
+        let first = push_buffer(&mut buffers, &conn);
+        push_buffer(&mut buffers, &conn);
+
+        assert_eq!(buffers.free_buffer(), Some(0));
+        buffers.attach(0, &surface);
+        assert!(first.load(Ordering::Relaxed));
+        assert_eq!(buffers.free_buffer(), Some(1));
+        buffers.attach(1, &surface);
+        assert_eq!(buffers.free_buffer(), None);
+        assert_eq!(buffers.attached, Some(1));
+
+        // What the release event of the compositor does
+        first.store(false, Ordering::Relaxed);
+        assert_eq!(buffers.free_buffer(), Some(0));
+
+        buffers.clear_contents();
+        assert!(buffers.contents(0).is_none() && buffers.contents(1).is_none());
+
+        // Buffers of another size are dropped
+        buffers.set_layout(8, 8, wl_shm::Format::Argb8888);
+        assert_eq!(buffers.free_buffer(), None);
+        assert_eq!(buffers.attached, None);
+    }
+}
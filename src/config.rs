@@ -0,0 +1,211 @@
+//! Loading the config file and the files it includes
+
+use std::path::{Path, PathBuf};
+
+use toml_parser::Source;
+use toml_parser::parser::{EventKind, parse_document};
+use walk_bg::{Config, types::FIELD_ALIASES};
+
+/// Read and validate the config file at `path`
+pub fn load_config(path: &Path) -> Result<Config, String> {
+    let (config, keys) = load_layer(path, &mut Vec::new())?;
+    let config = with_theme(config, &keys);
+    config.validate()?;
+    Ok(config)
+}
+
+/// Parse and validate a TOML config, includes are resolved from `dir`
+pub fn parse_config(contents: &str, dir: &Path) -> Result<Config, String> {
+    let (config, keys) = parse_layer(contents, dir, &mut Vec::new())?;
+    let config = with_theme(config, &keys);
+    config.validate()?;
+    Ok(config)
+}
+
+/// Put `config` on top of the colors of its theme, so the colors it sets
+/// itself win. `keys` are the keys the config files set.
+fn with_theme(config: Config, keys: &[String]) -> Config {
+    let Some(theme) = config.get_theme() else {
+        return config;
+    };
+    let mut themed = Config::default();
+    themed.apply_theme(theme);
+    themed.merge(config, keys);
+    themed
+}
+
+/// Load the file at `path` on top of its includes. `stack` holds the files
+/// that are currently being loaded, to catch include cycles
+fn load_layer(path: &Path, stack: &mut Vec<PathBuf>) -> Result<(Config, Vec<String>), String> {
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    if stack.contains(&canonical) {
+        let cycle: Vec<_> = stack
+            .iter()
+            .chain([&canonical])
+            .map(|path| path.display().to_string())
+            .collect();
+        return Err(format!("include cycle: {}", cycle.join(" -> ")));
+    }
+
+    let contents = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+
+    stack.push(canonical);
+    let layer = parse_layer(&contents, dir, stack);
+    stack.pop();
+    layer
+}
+
+/// Parse `contents` on top of its includes, returns the merged config and
+/// every key that was set along the way
+fn parse_layer(
+    contents: &str,
+    dir: &Path,
+    stack: &mut Vec<PathBuf>,
+) -> Result<(Config, Vec<String>), String> {
+    let mut layer: Config = facet_toml::from_str(contents).map_err(|e| e.to_string())?;
+    layer.resolve_paths(dir);
+
+    let mut config = Config::default();
+    let mut keys = Vec::new();
+    for include in layer.get_include() {
+        let (included, included_keys) = load_layer(&dir.join(include), stack)?;
+        config.merge(included, &included_keys);
+        keys.extend(included_keys);
+    }
+
+    let layer_keys = top_level_keys(contents);
+    config.merge(layer, &layer_keys);
+    keys.extend(layer_keys);
+    Ok((config, keys))
+}
+
+/// Names of the keys set at the top level of a TOML document, tables count
+/// with their name and aliases with the field they stand for. `contents` has
+/// to be valid TOML.
+fn top_level_keys(contents: &str) -> Vec<String> {
+    let source = Source::new(contents);
+    let tokens: Vec<_> = source.lex().collect();
+    let mut events = Vec::new();
+    parse_document(&tokens, &mut events, &mut ());
+
+    let mut keys: Vec<String> = Vec::new();
+    // Nesting of arrays and inline tables, their keys and newlines don't count
+    let mut depth = 0;
+    // Whether the next key is the first one of a line or table header on the
+    // top level. Dotted keys only count with their first part.
+    let mut key_starts = true;
+    let mut in_table = false;
+    for event in &events {
+        match event.kind() {
+            EventKind::StdTableOpen | EventKind::ArrayTableOpen => {
+                in_table = true;
+                key_starts = true;
+            }
+            EventKind::ArrayOpen | EventKind::InlineTableOpen => depth += 1,
+            EventKind::ArrayClose | EventKind::InlineTableClose => depth -= 1,
+            EventKind::Newline if depth == 0 => key_starts = !in_table,
+            EventKind::SimpleKey if key_starts && depth == 0 => {
+                key_starts = false;
+                let mut key = String::new();
+                if let Some(raw) = source.get(event) {
+                    raw.decode_key(&mut key, &mut ());
+                }
+                if let Some((_, field)) = FIELD_ALIASES.iter().find(|(alias, _)| *alias == key) {
+                    key = field.to_string();
+                }
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+            _ => {}
+        }
+    }
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use walk_bg::utils::Rng;
+
+    #[test]
+    fn keys_of_a_layer() {
+        let contents = r##"
+# fg_color = "#ffffff"
+walks_per_minute = 60.0
+"bg_color" = "#000000"
+anchor = [
+    "top",
+    "left = right",
+]
+drift.x = 1.0
+include = [] # seed = 1
+
+[walker]
+seed = 2
+"##;
+        assert_eq!(
+            top_level_keys(contents),
+            [
+                "walks_per_minute",
+                "bg_color",
+                "anchor",
+                "drift",
+                "include",
+                "walker"
+            ]
+        );
+    }
+
+    #[test]
+    fn max_runtime_secs_sets_the_run_duration() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("base.toml"), "max_runtime_secs = 60\n").unwrap();
+        let config = parse_config("include = [\"base.toml\"]\n", dir.path()).unwrap();
+        assert_eq!(
+            config.get_run_duration(),
+            Some(std::time::Duration::from_secs(60))
+        );
+        assert_eq!(
+            top_level_keys("max_runtime_secs = 60"),
+            ["run_duration_secs"]
+        );
+    }
+
+    #[test]
+    fn hot_reload_sets_watch_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = parse_config("hot_reload = true\n", dir.path()).unwrap();
+        assert!(config.watch_config());
+        assert_eq!(top_level_keys("hot_reload = true"), ["watch_config"]);
+    }
+
+    #[test]
+    fn reload_keeps_the_walk_going() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("base.toml"), "walker_count = 3\n").unwrap();
+        let first = parse_config("include = [\"base.toml\"]\nseed = 7\n", dir.path()).unwrap();
+        let reloaded = parse_config(
+            "include = [\"base.toml\"]\nseed = 7\nwalks_per_minute = 120.0\n",
+            dir.path(),
+        )
+        .unwrap();
+        assert_eq!(reloaded.get_walker_count(), 3);
+        assert_eq!(reloaded.get_seed(), Some(7));
+        assert_eq!(reloaded.get_walks_per_minute(), 120.0);
+
+        let mut rng = Rng::from_seed(first.get_seed().unwrap());
+        let mut expected = rng.clone();
+        rng.next_u64();
+        expected.next_u64();
+        assert!(!rng.reseed(first.get_seed(), reloaded.get_seed()));
+        assert_eq!(rng.next_u64(), expected.next_u64());
+
+        let reseeded = parse_config("seed = 8\n", dir.path()).unwrap();
+        assert!(rng.reseed(reloaded.get_seed(), reseeded.get_seed()));
+        assert_eq!(rng.next_u64(), Rng::from_seed(8).next_u64());
+    }
+}
@@ -0,0 +1,128 @@
+//! Querying the running daemon through a Unix socket or a status file
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::app::App;
+
+/// How long a client gets to send its command
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Where the control socket is created
+pub fn socket_path() -> PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("walk_bg.sock")
+}
+
+/// Listens for one-line commands and answers each with one line
+pub struct ControlSocket {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl ControlSocket {
+    pub fn new(path: &Path) -> std::io::Result<Self> {
+        // A socket left behind by a previous run would make binding fail
+        if UnixStream::connect(path).is_err() {
+            let _ = std::fs::remove_file(path);
+        }
+
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        Ok(ControlSocket {
+            listener,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Answer every pending connection with the reply of `respond`
+    pub fn poll(&self, mut respond: impl FnMut(&str) -> String) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = Self::answer(stream, &mut respond) {
+                        log::warn!("Failed to answer control connection: {e}");
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::warn!("Failed to accept control connection: {e}");
+                    break;
+                }
+            }
+        }
+    }
+
+    fn answer(stream: UnixStream, respond: &mut impl FnMut(&str) -> String) -> std::io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+        let mut command = String::new();
+        BufReader::new(&stream).read_line(&mut command)?;
+        let reply = respond(command.trim());
+        (&stream).write_all(format!("{reply}\n").as_bytes())
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Reply to a single control command.
+///
+/// `stats` returns a JSON object like
+///
+/// ```json
+/// {"steps":1200,"visits":1201,"coverage":0.0312,"positions":[[48,27]],"grid":{"width":97,"height":55},"fps":1.0,"uptime_secs":1200.4}
+/// ```
+///
+/// where `coverage` is the fraction of visited cells. Unknown commands get
+/// `{"error":"unknown command"}`.
+pub fn handle_command(app: &App, command: &str) -> String {
+    match command {
+        "stats" => stats(app),
+        _ => r#"{"error":"unknown command"}"#.to_string(),
+    }
+}
+
+/// Statistics about the running walk as a single line of JSON
+pub fn stats(app: &App) -> String {
+    let (visits, coverage, width, height) = app.get_grid().map_or((0, 0.0, 0, 0), |grid| {
+        (
+            grid.total_visits(),
+            grid.coverage(),
+            grid.get_width(),
+            grid.get_height(),
+        )
+    });
+    let positions = app
+        .get_positions()
+        .iter()
+        .map(|(x, y)| format!("[{x},{y}]"))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(
+        r#"{{"steps":{},"visits":{},"coverage":{:.4},"positions":[{}],"grid":{{"width":{},"height":{}}},"fps":{:.1},"uptime_secs":{:.1}}}"#,
+        app.get_total_steps(),
+        visits,
+        coverage,
+        positions,
+        width,
+        height,
+        app.fps(),
+        app.uptime().as_secs_f64(),
+    )
+}
+
+/// Write the statistics to `path`, through a temporary file so readers never
+/// see half of it
+pub fn write_status(path: &Path, app: &App) -> std::io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, stats(app) + "\n")?;
+    std::fs::rename(tmp_path, path)
+}
@@ -1,123 +1,1138 @@
 use crate::types;
+use crate::utils::BoundaryMode;
+
+/// Shapes a dot can be drawn as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DotShape {
+    /// A filled circle
+    Circle,
+    /// The outline of a circle
+    Ring,
+    /// A filled square
+    Square,
+    /// A filled square standing on a corner
+    Diamond,
+    /// A horizontal and a vertical line through the center
+    Cross,
+}
+
+impl DotShape {
+    /// Names accepted by the shape config fields
+    pub const NAMES: &[&str] = &["circle", "ring", "square", "diamond", "cross"];
+
+    /// Look up a shape by its config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "circle" => Some(DotShape::Circle),
+            "ring" => Some(DotShape::Ring),
+            "square" => Some(DotShape::Square),
+            "diamond" => Some(DotShape::Diamond),
+            "cross" => Some(DotShape::Cross),
+            _ => None,
+        }
+    }
+
+    /// Whether the pixel at (dx, dy) from the center belongs to a dot of this shape
+    fn covers(self, dx: i32, dy: i32, radius: i32) -> bool {
+        // A dot of radius 0 is its center pixel, whatever the shape
+        if radius == 0 {
+            return dx == 0 && dy == 0;
+        }
+        let distance = (dx * dx + dy * dy) as f32;
+        let outer = (radius * radius) as f32;
+        match self {
+            DotShape::Circle => distance <= outer,
+            DotShape::Ring => {
+                let inner = (radius as f32 - 1.5).max(0.0);
+                distance <= outer && distance > inner * inner
+            }
+            // Only pixels within the radius are ever tested
+            DotShape::Square => true,
+            DotShape::Diamond => dx.abs() + dy.abs() <= radius,
+            DotShape::Cross => dx == 0 || dy == 0,
+        }
+    }
+}
+
+/// How visited neighbors are connected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionStyle {
+    /// Lines to the horizontal and vertical neighbors
+    Grid,
+    /// Lines to the diagonal neighbors
+    Web,
+}
+
+impl ConnectionStyle {
+    /// Names accepted by the `connection_style` config field
+    pub const NAMES: &[&str] = &["grid", "web"];
+
+    /// Look up a connection style by its config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "grid" => Some(ConnectionStyle::Grid),
+            "web" => Some(ConnectionStyle::Web),
+            _ => None,
+        }
+    }
+}
+
+/// Pixel formats the renderer can write
+///
+/// Like all `wl_shm` formats they are stored little-endian, so every pixel is
+/// blue, green, red and alpha (or padding) in memory. The blending helpers rely
+/// on that order, new formats have to keep it or convert after rendering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 8 bits each of alpha, red, green and blue
+    Argb8888,
+    /// Like `Argb8888`, but the compositor ignores the alpha channel
+    Xrgb8888,
+}
+
+impl PixelFormat {
+    /// Names accepted by the `pixel_format` config field
+    pub const NAMES: &[&str] = &["argb8888", "xrgb8888"];
+
+    /// Look up a pixel format by its config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "argb8888" => Some(PixelFormat::Argb8888),
+            "xrgb8888" => Some(PixelFormat::Xrgb8888),
+            _ => None,
+        }
+    }
+
+    /// The bytes of a pixel with the given channels, in memory order
+    pub fn pack(self, a: u8, r: u8, g: u8, b: u8) -> [u8; 4] {
+        match self {
+            PixelFormat::Argb8888 => [b, g, r, a],
+            // Without alpha every pixel is opaque
+            PixelFormat::Xrgb8888 => [b, g, r, 0xff],
+        }
+    }
+
+    /// The bytes of a pixel with an ARGB color like the config colors
+    pub fn encode(self, color: u32) -> [u8; 4] {
+        let (a, r, g, b) = channels(color);
+        self.pack(a, r, g, b)
+    }
+}
+
+/// Alpha, red, green and blue of an ARGB color
+pub fn channels(color: u32) -> (u8, u8, u8, u8) {
+    (
+        (color >> 24) as u8,
+        (color >> 16) as u8,
+        (color >> 8) as u8,
+        color as u8,
+    )
+}
+
+/// How a dot is combined with the pixels below it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Draw over the pixels below using the alpha of the color
+    Over,
+    /// Overwrite the pixels below
+    Replace,
+    /// Add the dot color to the pixels below, saturating at white
+    Add,
+}
+
+impl BlendMode {
+    /// Names accepted by the `blend_mode` config field
+    pub const NAMES: &[&str] = &["over", "replace", "add"];
+
+    /// Look up a blend mode by its config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "over" => Some(BlendMode::Over),
+            "replace" => Some(BlendMode::Replace),
+            "add" => Some(BlendMode::Add),
+            _ => None,
+        }
+    }
+}
+
+/// How dots that are larger than the space between them are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RadiusOverlap {
+    /// Shrink dots to half the spacing so they never overlap
+    Clip,
+    /// Draw dots over each other with alpha blending
+    Blend,
+    /// Draw dots over each other, the later one wins
+    Allow,
+}
+
+impl RadiusOverlap {
+    /// Names accepted by the `radius_overlap` config field
+    pub const NAMES: &[&str] = &["clip", "blend", "allow"];
+
+    /// Look up an overlap mode by its config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "clip" => Some(RadiusOverlap::Clip),
+            "blend" => Some(RadiusOverlap::Blend),
+            "allow" => Some(RadiusOverlap::Allow),
+            _ => None,
+        }
+    }
+}
 
 pub fn draw_dot_grid(
-    mmap: &mut memmap2::MmapMut,
+    buffer: &mut [u8],
     width: u32,
     height: u32,
-    config: types::Config,
+    config: &types::Config,
     grid: &types::Grid,
-    current_pos: (u32, u32),
+    positions: &[(u32, u32)],
+    ghosts: &[(u32, u32)],
 ) {
-    let bg_color = config.get_bg_color().to_le_bytes(); // BGRA
+    draw_dot_grid_region(
+        buffer,
+        width,
+        height,
+        config,
+        grid,
+        positions,
+        ghosts,
+        (0, 0, width, height),
+    );
+}
 
-    let dot_color = config.get_fg_color().to_le_bytes(); // BGRA
+/// Redraw only the part of the dot grid inside `region`, given as (x, y,
+/// width, height) in pixels. Everything outside of it is left untouched, the
+/// pixels inside end up exactly like after a full [`draw_dot_grid`].
+#[allow(clippy::too_many_arguments)]
+pub fn draw_dot_grid_region(
+    buffer: &mut [u8],
+    width: u32,
+    height: u32,
+    config: &types::Config,
+    grid: &types::Grid,
+    positions: &[(u32, u32)],
+    ghosts: &[(u32, u32)],
+    region: (u32, u32, u32, u32),
+) {
+    let region = clamp_region(region, width, height);
+    if region.2 == 0 || region.3 == 0 {
+        return;
+    }
 
-    let dot_radius = 2;
+    let format = config.get_pixel_format();
 
-    for y in 0..height {
-        for x in 0..width {
-            let offset = (y * width + x) as usize * 4;
-            mmap[offset] = bg_color[0]; // B
-            mmap[offset + 1] = bg_color[1]; // G
-            mmap[offset + 2] = bg_color[2]; // R
-            mmap[offset + 3] = bg_color[3]; // A
-        }
-    }
-
-    let spacing = config.get_pixels_per_point();
-
-    let grid_width = (width / spacing) + 1;
-    let grid_height = (height / spacing) + 1;
-
-    let connection_color = [
-        (dot_color[0] as f32 * 0.5) as u8, // B
-        (dot_color[1] as f32 * 0.5) as u8, // G
-        (dot_color[2] as f32 * 0.5) as u8, // R
-        0xff,                              // A
-    ];
-
-    (0..grid_height)
-        .flat_map(|grid_y| (0..grid_width).map(move |grid_x| (grid_x, grid_y)))
-        .for_each(|(grid_x, grid_y)| {
-            let visit_count = grid.get_visits(grid_x, grid_y);
-
-            let intensity = (visit_count as f32 / 10.0).min(1.0);
-            let r = (dot_color[2] as f32 + (255.0 - dot_color[2] as f32) * intensity) as u8;
-            let g = (dot_color[1] as f32 + (200.0 - dot_color[1] as f32) * intensity) as u8;
-            let b = (dot_color[0] as f32 + (100.0 - dot_color[0] as f32) * intensity) as u8;
-
-            let (r, g, b) = if (grid_x, grid_y) == current_pos && config.display_active_field() {
-                let highlight_colors = config.get_active_color().to_le_bytes(); // BGRA
-                (
-                    highlight_colors[2],
-                    highlight_colors[1],
-                    highlight_colors[0],
-                )
+    let (_, bg_r, bg_g, bg_b) = channels(config.get_bg_color());
+
+    let (fg_a, fg_r, fg_g, fg_b) = channels(config.get_fg_color());
+
+    let dot_radius = dot_radius(config, width, height);
+
+    fill_region(buffer, width, region, format.encode(config.get_bg_color()));
+
+    let (spacing_x, spacing_y) = config.get_spacing();
+
+    let (grid_width, grid_height) = config.grid_size(width, height);
+
+    // Scale the alpha of a connection color by `connection_alpha`
+    let connection_alpha = config.get_connection_alpha() as u32;
+    let line_alpha = |alpha: u8| (alpha as u32 * connection_alpha / 255) as u8;
+    // Scale the alpha of a dot color by `dot_alpha`
+    let dot_alpha = config.get_dot_alpha() as u32;
+    let color_alpha = |alpha: u8| (alpha as u32 * dot_alpha / 255) as u8;
+    let connection_color = match config.get_connection_color() {
+        Some(color) => {
+            let (a, r, g, b) = channels(color);
+            premultiply(format.pack(line_alpha(color_alpha(a)), r, g, b))
+        }
+        None => premultiply(format.pack(
+            line_alpha(color_alpha(fg_a)),
+            (fg_r as f32 * 0.5) as u8,
+            (fg_g as f32 * 0.5) as u8,
+            (fg_b as f32 * 0.5) as u8,
+        )),
+    };
+
+    let wrap = config.get_boundary() == BoundaryMode::Wrap;
+    let style = config.get_connection_style();
+    let overlap = config.get_radius_overlap();
+    let blend_mode = config.get_blend_mode();
+    let write_pixel: fn(&mut [u8], &[u8; 4]) = match blend_mode {
+        BlendMode::Add => add_pixel,
+        BlendMode::Over => blend_over,
+        BlendMode::Replace if overlap == RadiusOverlap::Blend => blend_over,
+        BlendMode::Replace => |pixel, color| pixel.copy_from_slice(color),
+    };
+    // Added connections would brighten every dot they end in, so only "over"
+    // blends them
+    let write_line = line_writer(blend_mode);
+
+    let cells =
+        (0..grid_height).flat_map(|grid_y| (0..grid_width).map(move |grid_x| (grid_x, grid_y)));
+
+    // Whether the box from (x0, y0) to (x1, y1) touches the region
+    let (region_x, region_y) = (region.0 as i32, region.1 as i32);
+    let (region_right, region_bottom) = (region_x + region.2 as i32, region_y + region.3 as i32);
+    let touches = |x0: i32, y0: i32, x1: i32, y1: i32| -> bool {
+        x1 >= region_x && x0 < region_right && y1 >= region_y && y0 < region_bottom
+    };
+
+    let max_intensity_visits = config.get_max_intensity_visits();
+    let (heat_a, heat_r, heat_g, heat_b) = channels(config.get_heat_color());
+
+    // Alpha and color of a dot before the active highlight and the vignette
+    let cell_color = |visit_count: u8| -> (u8, u8, u8, u8) {
+        if visit_count == 0
+            && let Some(color) = config.get_unvisited_color()
+        {
+            channels(color)
+        } else if config.heatmap() {
+            let intensity = if max_intensity_visits == 0 {
+                1.0
             } else {
-                (r, g, b)
+                (visit_count as f32 / max_intensity_visits as f32).min(1.0)
             };
+            (
+                (fg_a as f32 + (heat_a as f32 - fg_a as f32) * intensity) as u8,
+                (fg_r as f32 + (heat_r as f32 - fg_r as f32) * intensity) as u8,
+                (fg_g as f32 + (heat_g as f32 - fg_g as f32) * intensity) as u8,
+                (fg_b as f32 + (heat_b as f32 - fg_b as f32) * intensity) as u8,
+            )
+        } else {
+            (fg_a, fg_r, fg_g, fg_b)
+        }
+    };
 
-            let dot_color = [b, g, r, 0xff]; // BGRA
+    // With gradients each end of a connection takes its dot's color at half
+    // brightness, otherwise both ends use the connection color
+    let gradient = config.gradient_connections();
+    let endpoint_color = |grid_x: u32, grid_y: u32| -> [u8; 4] {
+        if !gradient {
+            return connection_color;
+        }
+        let (a, r, g, b) = cell_color(grid.get_visits(grid_x, grid_y));
+        premultiply(format.pack(
+            line_alpha(color_alpha(a)),
+            (r as f32 * 0.5) as u8,
+            (g as f32 * 0.5) as u8,
+            (b as f32 * 0.5) as u8,
+        ))
+    };
 
-            let center_x = grid_x * spacing;
-            let center_y = grid_y * spacing;
+    // Draw all connections before the dots, so dots always sit on top
+    if config.connect_dots() {
+        cells.clone().for_each(|(grid_x, grid_y)| {
+            if grid.get_visits(grid_x, grid_y) == 0 {
+                return;
+            }
+            let center_x = grid_x * spacing_x;
+            let center_y = grid_y * spacing_y;
+            // Connections only go right and down, apart from the ones wrapping
+            // around the edges
+            let wraps = wrap && (grid_x + 1 == grid_width || grid_y + 1 == grid_height);
+            if !wraps
+                && !touches(
+                    center_x as i32 - spacing_x as i32,
+                    center_y as i32,
+                    (center_x + spacing_x) as i32,
+                    (center_y + spacing_y) as i32,
+                )
+            {
+                return;
+            }
+            let color = endpoint_color(grid_x, grid_y);
 
-            if config.connect_dots() && visit_count > 0 {
+            if style == ConnectionStyle::Grid {
                 if grid_x + 1 < grid_width && grid.get_visits(grid_x + 1, grid_y) > 0 {
-                    let neighbor_x = ((grid_x + 1) * spacing) as i32;
-                    draw_line(
-                        mmap,
+                    let neighbor_x = ((grid_x + 1) * spacing_x) as i32;
+                    draw_line_in(
+                        buffer,
                         width,
-                        height,
+                        region,
                         center_x as i32,
                         center_y as i32,
                         neighbor_x,
                         center_y as i32,
-                        &connection_color,
+                        &color,
+                        &endpoint_color(grid_x + 1, grid_y),
+                        write_line,
+                    );
+                } else if wrap && grid_x + 1 == grid_width && grid.get_visits(0, grid_y) > 0 {
+                    // Connect across the wrap with one segment leaving each edge
+                    let step = spacing_x as i32;
+                    let (x, y) = (center_x as i32, center_y as i32);
+                    let neighbor = endpoint_color(0, grid_y);
+                    draw_line_in(
+                        buffer,
+                        width,
+                        region,
+                        x,
+                        y,
+                        x + step,
+                        y,
+                        &color,
+                        &neighbor,
+                        write_line,
+                    );
+                    draw_line_in(
+                        buffer, width, region, -step, y, 0, y, &color, &neighbor, write_line,
                     );
                 }
 
                 if grid_y + 1 < grid_height && grid.get_visits(grid_x, grid_y + 1) > 0 {
-                    let neighbor_y = ((grid_y + 1) * spacing) as i32;
-                    draw_line(
-                        mmap,
+                    let neighbor_y = ((grid_y + 1) * spacing_y) as i32;
+                    draw_line_in(
+                        buffer,
                         width,
-                        height,
+                        region,
                         center_x as i32,
                         center_y as i32,
                         center_x as i32,
                         neighbor_y,
-                        &connection_color,
+                        &color,
+                        &endpoint_color(grid_x, grid_y + 1),
+                        write_line,
+                    );
+                } else if wrap && grid_y + 1 == grid_height && grid.get_visits(grid_x, 0) > 0 {
+                    let step = spacing_y as i32;
+                    let (x, y) = (center_x as i32, center_y as i32);
+                    let neighbor = endpoint_color(grid_x, 0);
+                    draw_line_in(
+                        buffer,
+                        width,
+                        region,
+                        x,
+                        y,
+                        x,
+                        y + step,
+                        &color,
+                        &neighbor,
+                        write_line,
+                    );
+                    draw_line_in(
+                        buffer, width, region, x, -step, x, 0, &color, &neighbor, write_line,
                     );
                 }
             }
 
-            (-dot_radius..=dot_radius)
-                .flat_map(|dy| {
-                    (-dot_radius..=dot_radius)
-                        .map(move |dx| (dx, dy))
-                        .filter(|(dx, dy)| {
-                            (dx * dx + dy * dy) as f32 <= (dot_radius * dot_radius) as f32
-                        })
-                })
-                .for_each(|(dx, dy)| {
-                    let px = center_x as i32 + dx;
-                    let py = center_y as i32 + dy;
-
-                    if px >= 0 && px < width as i32 && py >= 0 && py < height as i32 {
-                        let offset = (py as u32 * width + px as u32) as usize * 4;
-                        mmap[offset] = dot_color[0]; // B
-                        mmap[offset + 1] = dot_color[1]; // G
-                        mmap[offset + 2] = dot_color[2]; // R
-                        mmap[offset + 3] = dot_color[3]; // A
+            // Only look downwards so every diagonal is drawn once
+            if style == ConnectionStyle::Web || config.diagonals() {
+                let (x, y) = (center_x as i32, center_y as i32);
+                let (step_x, step_y) = (spacing_x as i32, spacing_y as i32);
+                if grid_y + 1 < grid_height {
+                    if grid_x + 1 < grid_width && grid.get_visits(grid_x + 1, grid_y + 1) > 0 {
+                        draw_line_in(
+                            buffer,
+                            width,
+                            region,
+                            x,
+                            y,
+                            x + step_x,
+                            y + step_y,
+                            &color,
+                            &endpoint_color(grid_x + 1, grid_y + 1),
+                            write_line,
+                        );
                     }
-                });
+                    if grid_x > 0 && grid.get_visits(grid_x - 1, grid_y + 1) > 0 {
+                        draw_line_in(
+                            buffer,
+                            width,
+                            region,
+                            x,
+                            y,
+                            x - step_x,
+                            y + step_y,
+                            &color,
+                            &endpoint_color(grid_x - 1, grid_y + 1),
+                            write_line,
+                        );
+                    }
+                }
+            }
         });
+    }
+
+    // Squared distance from the center per column and row, normalized to 0..=1
+    let vignette = config.get_vignette();
+    let falloff = |count: u32| -> Vec<f32> {
+        let center = (count - 1) as f32 / 2.0;
+        (0..count)
+            .map(|i| {
+                if center == 0.0 {
+                    0.0
+                } else {
+                    ((i as f32 - center) / center).powi(2)
+                }
+            })
+            .collect()
+    };
+    let (column_falloff, row_falloff) = if vignette > 0.0 {
+        (falloff(grid_width), falloff(grid_height))
+    } else {
+        (Vec::new(), Vec::new())
+    };
+
+    // Largest radius a dot can have, dots further away can't reach the region
+    let reach = config
+        .get_active_radius()
+        .map_or(dot_radius, |radius| dot_radius.max(radius as i32));
+
+    cells.for_each(|(grid_x, grid_y)| {
+        let (center_x, center_y) = ((grid_x * spacing_x) as i32, (grid_y * spacing_y) as i32);
+        if !touches(
+            center_x - reach,
+            center_y - reach,
+            center_x + reach,
+            center_y + reach,
+        ) {
+            return;
+        }
+
+        let (a, r, g, b) = cell_color(grid.get_visits(grid_x, grid_y));
+
+        let is_active = config.display_active_field() && positions.contains(&(grid_x, grid_y));
+        let ghost_age = if config.display_active_field() {
+            ghosts.iter().position(|&ghost| ghost == (grid_x, grid_y))
+        } else {
+            None
+        };
+
+        let (active_a, active_r, active_g, active_b) = channels(config.get_active_color());
+        let (a, r, g, b) = if is_active {
+            (active_a, active_r, active_g, active_b)
+        } else if let Some(age) = ghost_age {
+            // Older ghosts sink further back into the dot color
+            let strength = 1.0 - (age + 1) as f32 / (ghosts.len() + 1) as f32;
+            let mix = |channel: u8, highlight: u8| {
+                (channel as f32 + (highlight as f32 - channel as f32) * strength) as u8
+            };
+            (
+                mix(a, active_a),
+                mix(r, active_r),
+                mix(g, active_g),
+                mix(b, active_b),
+            )
+        } else if vignette > 0.0 {
+            // Fade toward the background the further the dot is from the center
+            let distance = (column_falloff[grid_x as usize] + row_falloff[grid_y as usize]) / 2.0;
+            let factor = (1.0 - vignette * distance).clamp(0.0, 1.0);
+            let fade = |channel: u8, background: u8| {
+                (background as f32 + (channel as f32 - background as f32) * factor) as u8
+            };
+            (a, fade(r, bg_r), fade(g, bg_g), fade(b, bg_b))
+        } else {
+            (a, r, g, b)
+        };
+
+        let dot_color = premultiply(format.pack(color_alpha(a), r, g, b));
+
+        let (shape, radius) = if is_active {
+            (
+                config.get_active_shape(),
+                config
+                    .get_active_radius()
+                    .map_or(dot_radius, |radius| radius as i32),
+            )
+        } else {
+            (config.get_dot_shape(), dot_radius)
+        };
+        let radius = match overlap {
+            RadiusOverlap::Clip => radius.min(spacing_x.min(spacing_y) as i32 / 2),
+            RadiusOverlap::Blend | RadiusOverlap::Allow => radius,
+        };
+
+        fill_dot(
+            buffer,
+            width,
+            region,
+            (center_x, center_y),
+            shape,
+            radius,
+            &dot_color,
+            write_pixel,
+        );
+    });
+}
+
+/// The regions, as (x, y, width, height), that change when the given cells
+/// change. Each region covers a cell's dot and the connections to its
+/// neighbors, connections wrapping around the edges include the other edge.
+///
+/// Overlapping regions are merged into their bounding box, so nearby cells
+/// end up in a single region.
+pub fn dirty_regions(
+    config: &types::Config,
+    width: u32,
+    height: u32,
+    cells: &[(u32, u32)],
+) -> Vec<(u32, u32, u32, u32)> {
+    let (spacing_x, spacing_y) = config.get_spacing();
+    let (grid_width, grid_height) = config.grid_size(width, height);
+    let dot_radius = dot_radius(config, width, height);
+    let radius = config
+        .get_active_radius()
+        .map_or(dot_radius, |radius| dot_radius.max(radius as i32)) as u32;
+    let (reach_x, reach_y) = (spacing_x.max(radius), spacing_y.max(radius));
+    let wrap = config.connect_dots() && config.get_boundary() == BoundaryMode::Wrap;
+
+    let region = |(grid_x, grid_y): (u32, u32)| {
+        let (center_x, center_y) = (grid_x * spacing_x, grid_y * spacing_y);
+        let (x, y) = (
+            center_x.saturating_sub(reach_x),
+            center_y.saturating_sub(reach_y),
+        );
+        clamp_region(
+            (x, y, center_x + reach_x + 1 - x, center_y + reach_y + 1 - y),
+            width,
+            height,
+        )
+    };
+
+    let mut regions = Vec::new();
+    for &(grid_x, grid_y) in cells {
+        regions.push(region((grid_x, grid_y)));
+        if wrap && (grid_x == 0 || grid_x + 1 == grid_width) {
+            regions.push(region((grid_width - 1 - grid_x, grid_y)));
+        }
+        if wrap && (grid_y == 0 || grid_y + 1 == grid_height) {
+            regions.push(region((grid_x, grid_height - 1 - grid_y)));
+        }
+    }
+    merge_regions(regions)
+}
+
+/// Replace regions that overlap or touch by their bounding box until none do
+fn merge_regions(mut regions: Vec<(u32, u32, u32, u32)>) -> Vec<(u32, u32, u32, u32)> {
+    let touches = |a: (u32, u32, u32, u32), b: (u32, u32, u32, u32)| {
+        a.0 <= b.0 + b.2 && b.0 <= a.0 + a.2 && a.1 <= b.1 + b.3 && b.1 <= a.1 + a.3
+    };
+
+    let mut merged: Vec<(u32, u32, u32, u32)> = Vec::new();
+    while let Some(mut region) = regions.pop() {
+        // A grown region can touch ones that didn't touch it before
+        while let Some(index) = merged.iter().position(|&other| touches(region, other)) {
+            let other = merged.swap_remove(index);
+            let (x, y) = (region.0.min(other.0), region.1.min(other.1));
+            let right = (region.0 + region.2).max(other.0 + other.2);
+            let bottom = (region.1 + region.3).max(other.1 + other.3);
+            region = (x, y, right - x, bottom - y);
+        }
+        merged.push(region);
+    }
+    merged
+}
+
+/// Radius of the dots in pixels. Dots never need to be larger than the
+/// buffer, which keeps the fill loops from overflowing.
+fn dot_radius(config: &types::Config, width: u32, height: u32) -> i32 {
+    config.get_dot_radius().min(width.max(height)) as i32
+}
+
+/// Scale the color channels of a BGRA color by its alpha, as the compositor
+/// expects premultiplied alpha
+fn premultiply(color: [u8; 4]) -> [u8; 4] {
+    let alpha = color[3] as u32;
+    [
+        (color[0] as u32 * alpha / 255) as u8,
+        (color[1] as u32 * alpha / 255) as u8,
+        (color[2] as u32 * alpha / 255) as u8,
+        color[3],
+    ]
+}
+
+/// Draw the step from `from` to `to` in the compass color of its direction,
+/// on top of a finished frame.
+///
+/// Steps across a wrapped edge only get their dot, teleports aren't drawn.
+pub fn compass_step(
+    buffer: &mut [u8],
+    width: u32,
+    height: u32,
+    config: &types::Config,
+    from: (u32, u32),
+    to: (u32, u32),
+) {
+    let (grid_width, grid_height) = config.grid_size(width, height);
+    let bounds = config.walk_bounds_in(grid_width, grid_height);
+    let Some(direction) = crate::utils::step_direction(from, to, bounds) else {
+        return;
+    };
+    let (_, r, g, b) = channels(config.get_compass_color(direction));
+    let color = premultiply(
+        config
+            .get_pixel_format()
+            .pack(config.get_dot_alpha(), r, g, b),
+    );
+
+    let (spacing_x, spacing_y) = config.get_spacing();
+    let center = ((to.0 * spacing_x) as i32, (to.1 * spacing_y) as i32);
+    let previous = ((from.0 * spacing_x) as i32, (from.1 * spacing_y) as i32);
+    let (dx, dy) = (to.0 as i64 - from.0 as i64, to.1 as i64 - from.1 as i64);
+    if config.connect_dots() && dx.abs() <= 1 && dy.abs() <= 1 {
+        draw_line(
+            buffer, width, height, previous.0, previous.1, center.0, center.1, &color, &color,
+        );
+    }
+
+    fill_dot(
+        buffer,
+        width,
+        (0, 0, width, height),
+        center,
+        config.get_active_shape(),
+        active_radius(config, width, height),
+        &color,
+        |pixel, color| pixel.copy_from_slice(color),
+    );
+}
+
+/// Draw the active dot at `position` in grid cells, on top of a finished
+/// frame. The position can lie between cells while the dot slides from one
+/// to the next.
+pub fn active_marker(
+    buffer: &mut [u8],
+    width: u32,
+    height: u32,
+    config: &types::Config,
+    position: (f32, f32),
+) {
+    let (spacing_x, spacing_y) = config.get_spacing();
+    let center = (
+        (position.0 * spacing_x as f32).round() as i32,
+        (position.1 * spacing_y as f32).round() as i32,
+    );
+    let (a, r, g, b) = channels(config.get_active_color());
+    let alpha = (a as u32 * config.get_dot_alpha() as u32 / 255) as u8;
+    let color = premultiply(config.get_pixel_format().pack(alpha, r, g, b));
+
+    fill_dot(
+        buffer,
+        width,
+        (0, 0, width, height),
+        center,
+        config.get_active_shape(),
+        active_radius(config, width, height),
+        &color,
+        line_writer(config.get_blend_mode()),
+    );
+}
+
+/// Radius of the active dot, limited to half the spacing unless dots may
+/// overlap
+fn active_radius(config: &types::Config, width: u32, height: u32) -> i32 {
+    let (spacing_x, spacing_y) = config.get_spacing();
+    let radius = config
+        .get_active_radius()
+        .map_or(dot_radius(config, width, height), |radius| radius as i32);
+    match config.get_radius_overlap() {
+        RadiusOverlap::Clip => radius.min(spacing_x.min(spacing_y) as i32 / 2),
+        RadiusOverlap::Blend | RadiusOverlap::Allow => radius,
+    }
+}
+
+/// Draw only the cells of a snake's `body`, head first, on an empty background.
+///
+/// The head is the brightest segment, the rest dim down to half brightness
+/// toward the tail. Neighboring segments are joined if `connect_dots` is on.
+pub fn snake(
+    buffer: &mut [u8],
+    width: u32,
+    height: u32,
+    config: &types::Config,
+    body: &[(u32, u32)],
+) {
+    let format = config.get_pixel_format();
+    fill_background(buffer, width, height, format.encode(config.get_bg_color()));
+
+    let (spacing_x, spacing_y) = config.get_spacing();
+    let center = |(x, y): (u32, u32)| ((x * spacing_x) as i32, (y * spacing_y) as i32);
+    let write_pixel = line_writer(config.get_blend_mode());
+    let segment_color = |index: usize| {
+        let head = index == 0 && config.display_active_field();
+        let color = if head {
+            config.get_active_color()
+        } else {
+            config.get_fg_color()
+        };
+        let (a, r, g, b) = channels(color);
+        let brightness = 1.0 - 0.5 * index as f32 / body.len().max(2) as f32;
+        premultiply(format.pack(
+            (a as u32 * config.get_dot_alpha() as u32 / 255) as u8,
+            (r as f32 * brightness) as u8,
+            (g as f32 * brightness) as u8,
+            (b as f32 * brightness) as u8,
+        ))
+    };
+
+    // Draw from the tail, so newer segments sit on top where the body crosses
+    if config.connect_dots() {
+        for index in (1..body.len()).rev() {
+            let (from, to) = (body[index], body[index - 1]);
+            if from.0.abs_diff(to.0) > 1 || from.1.abs_diff(to.1) > 1 {
+                continue;
+            }
+            let (from, to) = (center(from), center(to));
+            draw_line_in(
+                buffer,
+                width,
+                (0, 0, width, height),
+                from.0,
+                from.1,
+                to.0,
+                to.1,
+                &segment_color(index),
+                &segment_color(index - 1),
+                write_pixel,
+            );
+        }
+    }
+    let dot_radius = dot_radius(config, width, height);
+    for index in (0..body.len()).rev() {
+        let radius = if index == 0 {
+            config
+                .get_active_radius()
+                .map_or(dot_radius, |radius| radius as i32)
+        } else {
+            dot_radius
+        };
+        fill_dot(
+            buffer,
+            width,
+            (0, 0, width, height),
+            center(body[index]),
+            if index == 0 {
+                config.get_active_shape()
+            } else {
+                config.get_dot_shape()
+            },
+            radius,
+            &segment_color(index),
+            write_pixel,
+        );
+    }
+}
+
+/// Draw the part of a single dot of the given shape and radius around `center`
+/// that lies inside `region`, combining each pixel with what is already there
+/// through `write_pixel`
+#[allow(clippy::too_many_arguments)]
+fn fill_dot(
+    buffer: &mut [u8],
+    width: u32,
+    region: (u32, u32, u32, u32),
+    center: (i32, i32),
+    shape: DotShape,
+    radius: i32,
+    color: &[u8; 4],
+    write_pixel: fn(&mut [u8], &[u8; 4]),
+) {
+    let (region_x, region_y) = (region.0 as i32, region.1 as i32);
+    let (region_right, region_bottom) = (region_x + region.2 as i32, region_y + region.3 as i32);
+    for dy in -radius..=radius {
+        let py = center.1 + dy;
+        if py < region_y || py >= region_bottom {
+            continue;
+        }
+
+        // Only the part of the row the dot can touch
+        let min_x = (center.0 - radius).max(region_x);
+        let max_x = (center.0 + radius).min(region_right - 1);
+        if min_x > max_x {
+            continue;
+        }
+        let row_start = (py as u32 * width + min_x as u32) as usize * 4;
+        let row_end = (py as u32 * width + max_x as u32 + 1) as usize * 4;
+
+        buffer[row_start..row_end]
+            .chunks_exact_mut(4)
+            .zip(min_x..=max_x)
+            .filter(|&(_, px)| shape.covers(px - center.0, dy, radius))
+            .for_each(|(pixel, _)| write_pixel(pixel, color));
+    }
+}
+
+/// How connections are written in `mode`: blended over what is below them in
+/// "over", overwriting it otherwise
+fn line_writer(mode: BlendMode) -> fn(&mut [u8], &[u8; 4]) {
+    match mode {
+        BlendMode::Over => blend_over,
+        BlendMode::Replace | BlendMode::Add => |pixel, color| pixel.copy_from_slice(color),
+    }
+}
+
+/// Draw the premultiplied `color` over `pixel`
+fn blend_over(pixel: &mut [u8], color: &[u8; 4]) {
+    let remaining = 255 - color[3] as u32;
+    pixel
+        .iter_mut()
+        .zip(color)
+        .for_each(|(dst, &src)| *dst = (src as u32 + *dst as u32 * remaining / 255) as u8);
+}
+
+/// Add `color` to `pixel`, channel by channel
+fn add_pixel(pixel: &mut [u8], color: &[u8; 4]) {
+    pixel
+        .iter_mut()
+        .zip(color)
+        .for_each(|(dst, &src)| *dst = dst.saturating_add(src));
+}
+
+/// Write `color` to the pixel at (x, y) through `write_pixel` if it is inside
+/// `region`
+#[allow(clippy::too_many_arguments)]
+fn set_pixel(
+    buffer: &mut [u8],
+    width: u32,
+    region: (u32, u32, u32, u32),
+    x: i32,
+    y: i32,
+    color: &[u8; 4],
+    write_pixel: fn(&mut [u8], &[u8; 4]),
+) {
+    let (region_x, region_y) = (region.0 as i32, region.1 as i32);
+    if x >= region_x
+        && x < region_x + region.2 as i32
+        && y >= region_y
+        && y < region_y + region.3 as i32
+    {
+        let offset = (y as u32 * width + x as u32) as usize * 4;
+        write_pixel(&mut buffer[offset..offset + 4], color);
+    }
+}
+
+/// Add a blurred copy of everything brighter than `background` on top of the
+/// frame, so dots and connections bleed light into their surroundings.
+///
+/// Uses a separable box blur of the given radius. This touches every pixel
+/// several times, so it is only cheap enough for small radii.
+pub fn glow(buffer: &mut [u8], width: u32, height: u32, radius: u32, background: [u8; 4]) {
+    let (width, height, radius) = (width as usize, height as usize, radius as usize);
+    let len = width * height * 4;
+    if radius == 0 || len == 0 || buffer.len() < len {
+        return;
+    }
+
+    // Only the light above the background should glow, alpha is left alone
+    let mut light: Vec<u32> = buffer[..len]
+        .iter()
+        .enumerate()
+        .map(|(i, &channel)| match i % 4 {
+            3 => 0,
+            c => channel.saturating_sub(background[c]) as u32,
+        })
+        .collect();
+    let mut blurred = vec![0u32; len];
+
+    for y in 0..height {
+        for c in 0..3 {
+            blur_line(&light, &mut blurred, y * width * 4 + c, 4, width, radius);
+        }
+    }
+    for x in 0..width {
+        for c in 0..3 {
+            blur_line(&blurred, &mut light, x * 4 + c, width * 4, height, radius);
+        }
+    }
+
+    buffer[..len]
+        .iter_mut()
+        .zip(light)
+        .for_each(|(channel, glow)| *channel = channel.saturating_add(glow.min(255) as u8));
+}
+
+/// Box blur `count` values starting at `start` that are `step` apart
+fn blur_line(src: &[u32], dst: &mut [u32], start: usize, step: usize, count: usize, radius: usize) {
+    let at = |i: usize| src[start + i * step];
+    let window = (2 * radius + 1) as u32;
+
+    let mut sum: u32 = (0..=radius.min(count - 1)).map(at).sum();
+    for i in 0..count {
+        dst[start + i * step] = sum / window;
+        if i + radius + 1 < count {
+            sum += at(i + radius + 1);
+        }
+        if i >= radius {
+            sum -= at(i - radius);
+        }
+    }
+}
+
+/// Scale every pixel by `alpha`, fading the frame towards transparent black
+pub fn fade(buffer: &mut [u8], alpha: f32) {
+    let alpha = alpha.clamp(0.0, 1.0);
+    buffer
+        .iter_mut()
+        .for_each(|channel| *channel = (*channel as f32 * alpha) as u8);
+}
+
+/// Rotation of the buffer relative to the surface, counter-clockwise like
+/// Wayland output transforms
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Normal,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl Rotation {
+    /// Size of the rotated buffer for a frame of `width` x `height`
+    pub fn buffer_size(self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Rotation::Normal | Rotation::Rotate180 => (width, height),
+            Rotation::Rotate90 | Rotation::Rotate270 => (height, width),
+        }
+    }
+
+    /// The rotation that turns a rotated buffer upright again
+    pub fn inverse(self) -> Rotation {
+        match self {
+            Rotation::Normal => Rotation::Normal,
+            Rotation::Rotate90 => Rotation::Rotate270,
+            Rotation::Rotate180 => Rotation::Rotate180,
+            Rotation::Rotate270 => Rotation::Rotate90,
+        }
+    }
+}
+
+/// Shrink the frame in `src`, `factor` times as wide and high as `dst`, into
+/// the `width` x `height` frame in `dst` by averaging each block of
+/// `factor` x `factor` pixels
+pub fn downsample(src: &[u8], dst: &mut [u8], width: u32, height: u32, factor: u32) {
+    let (width, height, factor) = (width as usize, height as usize, factor as usize);
+    let src_width = width * factor;
+    let count = (factor * factor) as u32;
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = [0u32; 4];
+            for src_y in y * factor..(y + 1) * factor {
+                let row = (src_y * src_width + x * factor) * 4;
+                for pixel in src[row..row + factor * 4].chunks_exact(4) {
+                    sum.iter_mut()
+                        .zip(pixel)
+                        .for_each(|(total, &channel)| *total += channel as u32);
+                }
+            }
+            let to = (y * width + x) * 4;
+            dst[to..to + 4]
+                .iter_mut()
+                .zip(sum)
+                .for_each(|(channel, total)| *channel = (total / count) as u8);
+        }
+    }
+}
+
+/// Copy the `width` x `height` frame in `src` into `dst`, rotated by `rotation`
+pub fn rotate(src: &[u8], dst: &mut [u8], width: u32, height: u32, rotation: Rotation) {
+    let (buffer_width, _) = rotation.buffer_size(width, height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let (bx, by) = match rotation {
+                Rotation::Normal => (x, y),
+                Rotation::Rotate90 => (height - 1 - y, x),
+                Rotation::Rotate180 => (width - 1 - x, height - 1 - y),
+                Rotation::Rotate270 => (y, width - 1 - x),
+            };
+            let from = (y * width + x) as usize * 4;
+            let to = (by * buffer_width + bx) as usize * 4;
+            dst[to..to + 4].copy_from_slice(&src[from..from + 4]);
+        }
+    }
+}
+
+/// Blend the outline of a circle around `center` over the buffer, using the
+/// midpoint circle algorithm. `color` is an ARGB color drawn at `alpha`
+/// opacity, so the circle can fade out in formats without alpha too.
+#[allow(clippy::too_many_arguments)]
+pub fn circle(
+    buffer: &mut [u8],
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    center: (i32, i32),
+    radius: i32,
+    color: u32,
+    alpha: u8,
+) {
+    let (_, r, g, b) = channels(color);
+    let mut color = format.pack(alpha, r, g, b);
+    // Blend by `alpha` even if the format drops it, the pixels stay opaque
+    color[3] = alpha;
+    let color = premultiply(color);
+    let mut plot = |x: i32, y: i32| {
+        let (x, y) = (center.0 + x, center.1 + y);
+        if x >= 0 && x < width as i32 && y >= 0 && y < height as i32 {
+            let offset = (y as u32 * width + x as u32) as usize * 4;
+            blend_over(&mut buffer[offset..offset + 4], &color);
+        }
+    };
+
+    let (mut x, mut y) = (radius, 0);
+    let mut err = 1 - radius;
+    while x >= y {
+        let mut points = [
+            (x, y),
+            (-x, y),
+            (x, -y),
+            (-x, -y),
+            (y, x),
+            (-y, x),
+            (y, -x),
+            (-y, -x),
+        ];
+        // On the axes and diagonals some mirrored points are the same, blending
+        // them twice would make those pixels stand out
+        points.sort_unstable();
+        for (i, &(dx, dy)) in points.iter().enumerate() {
+            if i == 0 || points[i - 1] != (dx, dy) {
+                plot(dx, dy);
+            }
+        }
+
+        y += 1;
+        if err < 0 {
+            err += 2 * y + 1;
+        } else {
+            x -= 1;
+            err += 2 * (y - x) + 1;
+        }
+    }
 }
 
-/// Draw a line between two points using Bresenham's line algorithm
-fn draw_line(
-    mmap: &mut memmap2::MmapMut,
+/// Clip a region given as (x, y, width, height) to the buffer
+fn clamp_region(region: (u32, u32, u32, u32), width: u32, height: u32) -> (u32, u32, u32, u32) {
+    let x = region.0.min(width);
+    let y = region.1.min(height);
+    (x, y, region.2.min(width - x), region.3.min(height - y))
+}
+
+/// Fill the pixels inside `region` with `color`
+fn fill_region(buffer: &mut [u8], width: u32, region: (u32, u32, u32, u32), color: [u8; 4]) {
+    let (x, y, region_width, region_height) = region;
+    for row in y..y + region_height {
+        let start = (row * width + x) as usize * 4;
+        buffer[start..start + region_width as usize * 4]
+            .chunks_exact_mut(4)
+            .for_each(|pixel| pixel.copy_from_slice(&color));
+    }
+}
+
+/// Fill the whole buffer with `color`
+pub fn fill_background(buffer: &mut [u8], width: u32, height: u32, color: [u8; 4]) {
+    buffer[..(width * height) as usize * 4]
+        .chunks_exact_mut(4)
+        .for_each(|pixel| pixel.copy_from_slice(&color));
+}
+
+/// Draw a line between two points using Bresenham's line algorithm, fading
+/// from `color` at the start to `end_color` at the end
+#[allow(clippy::too_many_arguments)]
+pub fn draw_line(
+    buffer: &mut [u8],
     width: u32,
     height: u32,
     x0: i32,
@@ -125,6 +1140,36 @@ fn draw_line(
     x1: i32,
     y1: i32,
     color: &[u8; 4],
+    end_color: &[u8; 4],
+) {
+    draw_line_in(
+        buffer,
+        width,
+        (0, 0, width, height),
+        x0,
+        y0,
+        x1,
+        y1,
+        color,
+        end_color,
+        |pixel, color| pixel.copy_from_slice(color),
+    );
+}
+
+/// Draw the part of a line that lies inside `region`, see [`draw_line`].
+/// Pixels are combined with what is already there through `write_pixel`.
+#[allow(clippy::too_many_arguments)]
+fn draw_line_in(
+    buffer: &mut [u8],
+    width: u32,
+    region: (u32, u32, u32, u32),
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32,
+    color: &[u8; 4],
+    end_color: &[u8; 4],
+    write_pixel: fn(&mut [u8], &[u8; 4]),
 ) {
     let dx = (x1 - x0).abs();
     let dy = (y1 - y0).abs();
@@ -134,14 +1179,19 @@ fn draw_line(
     let mut x = x0;
     let mut y = y0;
 
+    // Bresenham takes one step per pixel along the longer axis
+    let length = dx.max(dy).max(1);
+    let mut progress = 0;
+    let mut current = *color;
+
     loop {
-        if x >= 0 && x < width as i32 && y >= 0 && y < height as i32 {
-            let offset = (y as u32 * width + x as u32) as usize * 4;
-            mmap[offset] = color[0]; // B
-            mmap[offset + 1] = color[1]; // G
-            mmap[offset + 2] = color[2]; // R
-            mmap[offset + 3] = color[3]; // A
+        if color != end_color {
+            current = std::array::from_fn(|c| {
+                let (start, end) = (color[c] as i32, end_color[c] as i32);
+                (start + (end - start) * progress / length) as u8
+            });
         }
+        set_pixel(buffer, width, region, x, y, &current, write_pixel);
 
         if x == x1 && y == y1 {
             break;
@@ -156,5 +1206,199 @@ fn draw_line(
             err += dx;
             y += sy;
         }
+        progress += 1;
+    }
+}
+
+/// Paint a frame of `border_width` pixels along the edges of the buffer.
+///
+/// The width is clamped to half the smaller side, so a huge value fills the
+/// whole buffer instead of overflowing it.
+pub fn border(buffer: &mut [u8], width: u32, height: u32, border_width: u32, color: [u8; 4]) {
+    let (width, height) = (width as usize, height as usize);
+    let border_width = (border_width as usize).min(width.min(height).div_ceil(2));
+    if border_width == 0 || buffer.len() < width * height * 4 {
+        return;
+    }
+
+    for (y, row) in buffer.chunks_exact_mut(width * 4).take(height).enumerate() {
+        if y < border_width || y >= height - border_width {
+            row.chunks_exact_mut(4)
+                .for_each(|pixel| pixel.copy_from_slice(&color));
+        } else {
+            row[..border_width * 4]
+                .chunks_exact_mut(4)
+                .for_each(|pixel| pixel.copy_from_slice(&color));
+            row[(width - border_width) * 4..]
+                .chunks_exact_mut(4)
+                .for_each(|pixel| pixel.copy_from_slice(&color));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A buffer with every pixel filled with its byte from `ids`
+    fn pixels(ids: &[u8]) -> Vec<u8> {
+        ids.iter().flat_map(|&id| [id; 4]).collect()
+    }
+
+    fn config(toml: &str) -> types::Config {
+        let config: types::Config = facet_toml::from_str(toml).unwrap();
+        config.validate().unwrap();
+        config
+    }
+
+    /// The four bytes of the pixel at (x, y)
+    fn pixel(buffer: &[u8], width: u32, x: u32, y: u32) -> &[u8] {
+        let index = ((y * width + x) * 4) as usize;
+        &buffer[index..index + 4]
+    }
+
+    #[test]
+    fn dots_cover_junctions() {
+        let config = config(
+            "pixels_per_point = 10\ndot_radius = 4\nconnect_dots = true\nfg_color = \"#ff0000\"\nconnection_color = \"#00ff00\"",
+        );
+        // A plus with four connections meeting at (1, 1)
+        let mut grid = types::Grid::new(4, 4);
+        for (x, y) in [(1, 1), (0, 1), (2, 1), (1, 0), (1, 2)] {
+            grid.visit(x, y);
+        }
+        let (width, height) = (30, 30);
+        let mut buffer = vec![0; (width * height * 4) as usize];
+        draw_dot_grid(&mut buffer, width, height, &config, &grid, &[], &[]);
+
+        let dot = pixel(&buffer, width, 10, 10).to_vec();
+        let line = pixel(&buffer, width, 15, 10).to_vec();
+        assert_ne!(dot, line);
+        for y in 7..=13 {
+            for x in 7..=13 {
+                let (dx, dy) = (x as i32 - 10, y as i32 - 10);
+                if dx * dx + dy * dy < 9 {
+                    assert_eq!(pixel(&buffer, width, x, y), dot, "pixel {x},{y}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn region_matches_full_draw() {
+        let (width, height) = (40, 30);
+        let region = (7, 5, 13, 9);
+        for extra in ["", "connection_style = \"web\"", "boundary = \"wrap\""] {
+            let config = config(&format!(
+                "pixels_per_point = 5\ndot_radius = 2\nconnect_dots = true\n{extra}"
+            ));
+            let (grid_width, grid_height) = config.grid_size(width, height);
+            let mut grid = types::Grid::new(grid_width, grid_height);
+            let mut rng = crate::utils::Rng::from_seed(5);
+            for _ in 0..40 {
+                let x = (rng.next_u64() % grid_width as u64) as u32;
+                let y = (rng.next_u64() % grid_height as u64) as u32;
+                grid.visit(x, y);
+            }
+            let positions = [(2, 2)];
+
+            let mut full = vec![0; (width * height * 4) as usize];
+            draw_dot_grid(&mut full, width, height, &config, &grid, &positions, &[]);
+            let mut partial = vec![0x55; full.len()];
+            draw_dot_grid_region(
+                &mut partial,
+                width,
+                height,
+                &config,
+                &grid,
+                &positions,
+                &[],
+                region,
+            );
+
+            for y in 0..height {
+                for x in 0..width {
+                    let inside = (region.0..region.0 + region.2).contains(&x)
+                        && (region.1..region.1 + region.3).contains(&y);
+                    let expected = if inside {
+                        pixel(&full, width, x, y)
+                    } else {
+                        &[0x55; 4]
+                    };
+                    assert_eq!(
+                        pixel(&partial, width, x, y),
+                        expected,
+                        "{extra} pixel {x},{y}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn snake_draws_its_body_only() {
+        let config = config("pixels_per_point = 4\ndot_radius = 1\nsnake_length = 3");
+        let (width, height) = (24, 12);
+        let body = [(3, 1), (2, 1), (1, 1)];
+        let mut buffer = vec![0; (width * height * 4) as usize];
+        crate::render_snake(&config, &body, &mut buffer, width, height);
+
+        let background = config.get_pixel_format().encode(config.get_bg_color());
+        let drawn: Vec<_> = (0..height / 4)
+            .flat_map(|y| (0..width / 4).map(move |x| (x, y)))
+            .filter(|&(x, y)| pixel(&buffer, width, x * 4, y * 4) != background)
+            .collect();
+        assert_eq!(drawn, [(1, 1), (2, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn zero_radius_dots_are_one_pixel() {
+        for name in DotShape::NAMES {
+            let mut buffer = pixels(&[b'.'; 9]);
+            fill_dot(
+                &mut buffer,
+                3,
+                (0, 0, 3, 3),
+                (1, 1),
+                DotShape::from_name(name).unwrap(),
+                0,
+                &[b'#'; 4],
+                |pixel, color| pixel.copy_from_slice(color),
+            );
+            assert_eq!(buffer, pixels(b"....#...."), "{name}");
+        }
+    }
+
+    #[test]
+    fn border_frames_the_buffer() {
+        let mut buffer = pixels(&[b'.'; 7 * 6]);
+        border(&mut buffer, 7, 6, 2, [b'#'; 4]);
+        let rows = [
+            "#######", "#######", "##...##", "##...##", "#######", "#######",
+        ];
+        assert_eq!(buffer, pixels(rows.concat().as_bytes()));
+
+        let mut buffer = pixels(&[b'.'; 7 * 6]);
+        border(&mut buffer, 7, 6, u32::MAX, [b'#'; 4]);
+        assert_eq!(buffer, pixels(&[b'#'; 7 * 6]));
+    }
+
+    #[test]
+    fn rotate_normal_and_90() {
+        // A B C
+        // D E F
+        let frame = pixels(b"ABCDEF");
+        let mut buffer = vec![0; frame.len()];
+
+        rotate(&frame, &mut buffer, 3, 2, Rotation::Normal);
+        assert_eq!(buffer, frame);
+
+        rotate(&frame, &mut buffer, 3, 2, Rotation::Rotate90);
+        assert_eq!(Rotation::Rotate90.buffer_size(3, 2), (2, 3));
+        assert_eq!(buffer, pixels(b"DAEBFC"));
+
+        let mut upright = vec![0; frame.len()];
+        rotate(&buffer, &mut upright, 2, 3, Rotation::Rotate90.inverse());
+        assert_eq!(upright, frame);
     }
 }
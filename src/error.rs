@@ -0,0 +1,31 @@
+//! Errors that stop walk_bg
+
+use wayland_client::{ConnectError, DispatchError, backend::WaylandError, globals};
+
+#[derive(Debug, thiserror::Error)]
+pub enum WalkBgError {
+    #[error("couldn't connect to a Wayland compositor, is WAYLAND_DISPLAY set? ({0})")]
+    Connect(#[from] ConnectError),
+    #[error("couldn't list the compositor's globals: {0}")]
+    Globals(#[from] globals::GlobalError),
+    #[error("your compositor doesn't provide wl_compositor: {0}")]
+    Compositor(#[source] globals::BindError),
+    #[error("your compositor doesn't provide wl_shm: {0}")]
+    Shm(#[source] globals::BindError),
+    #[error("your compositor doesn't support wlr-layer-shell: {0}")]
+    LayerShell(#[source] globals::BindError),
+    #[error("lost the connection to the compositor: {0}")]
+    Dispatch(#[from] DispatchError),
+    #[error("lost the connection to the compositor: {0}")]
+    Wayland(#[from] WaylandError),
+    #[error("couldn't write the PNG: {0}")]
+    Png(#[from] png::EncodingError),
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Usage(&'static str),
+    #[error("couldn't load the config: {0}")]
+    Config(String),
+    #[error("nothing has been drawn yet")]
+    NothingDrawn,
+}
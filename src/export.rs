@@ -0,0 +1,37 @@
+//! Writing rendered frames to image files
+
+use std::path::Path;
+
+use crate::error::WalkBgError;
+
+/// Write a premultiplied `Argb8888` buffer to a PNG file at `path`
+pub fn write_png(path: &Path, buffer: &[u8], width: u32, height: u32) -> Result<(), WalkBgError> {
+    let len = (width * height * 4) as usize;
+    let rgba: Vec<u8> = buffer[..len]
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            // BGRA with premultiplied alpha to straight RGBA
+            let alpha = pixel[3] as u32;
+            let unpremultiply = |channel: u8| match alpha {
+                0 => 0,
+                _ => (channel as u32 * 255 / alpha).min(255) as u8,
+            };
+            [
+                unpremultiply(pixel[2]),
+                unpremultiply(pixel[1]),
+                unpremultiply(pixel[0]),
+                pixel[3],
+            ]
+        })
+        .collect();
+
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&rgba)?;
+    writer.finish()?;
+
+    Ok(())
+}
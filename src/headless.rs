@@ -0,0 +1,144 @@
+//! Running the walk without a Wayland connection
+
+use std::path::Path;
+
+use crate::error::WalkBgError;
+
+use walk_bg::{
+    Config, Grid,
+    utils::{Rng, WalkAlgorithm, start_positions, update_population},
+};
+
+/// Derive the spacing for a preview of the given size, like the surface does
+fn apply_spacing(config: &mut Config, width: u32, height: u32) {
+    config.set_pixels_per_point(config.spacing_for(width, height));
+    if config.fit_max_grid_cells(width, height) {
+        log::warn!(
+            "Grid would exceed max_grid_cells, using a spacing of {:?} instead",
+            config.get_spacing()
+        );
+    }
+}
+
+/// Run the walk for `preview_steps` steps and write the result to a PNG at `path`
+pub fn render_to_file(mut config: Config, path: &Path) -> Result<(), WalkBgError> {
+    let (width, height) = config.get_preview_size();
+    apply_spacing(&mut config, width, height);
+
+    let (grid_width, grid_height) = config.grid_size(width, height);
+    let mut grid = Grid::new(grid_width, grid_height);
+    grid.set_visit_increment(config.get_visit_increment());
+    grid.seed_from_pattern(config.get_seed_pattern());
+
+    let bounds = config.walk_bounds_in(grid_width, grid_height);
+    let start = (bounds.0 + bounds.2 / 2, bounds.1 + bounds.3 / 2);
+    let mut walkers = start_positions(start, config.get_walker_count(), bounds);
+    for &(x, y) in &walkers {
+        grid.visit(x, y);
+    }
+
+    let walk =
+        WalkAlgorithm::from_name(config.get_walk_algorithm()).unwrap_or(WalkAlgorithm::Uniform);
+    let mut rng = config.get_seed().map_or_else(Rng::new, Rng::from_seed);
+    let mut previous = None;
+    let mut ghosts = Vec::new();
+    let mut snake = vec![start];
+    let mut last_dirs = Vec::new();
+    for _ in 0..config.get_preview_steps() {
+        previous = Some(walkers[0]);
+        ghosts.insert(0, walkers[0]);
+        ghosts.truncate(config.get_ghost_steps() as usize);
+        last_dirs.resize(walkers.len(), None);
+        for (pos, last_dir) in walkers.iter_mut().zip(&mut last_dirs) {
+            *pos = walk.step(*pos, last_dir, bounds, &mut grid, &mut rng, &config);
+            grid.visit(pos.0, pos.1);
+        }
+        update_population(&mut walkers, bounds, &mut grid, &mut rng, &config);
+        snake.insert(0, walkers[0]);
+        snake.truncate(config.get_snake_length().unwrap_or(0) as usize);
+    }
+
+    let size = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(4))
+        .ok_or(WalkBgError::Usage("the preview doesn't fit into memory"))?;
+    let mut buffer = vec![0u8; size];
+    if config.get_snake_length().is_some() {
+        walk_bg::render_snake(&config, &snake, &mut buffer, width, height);
+    } else {
+        walk_bg::render_frame(
+            &config,
+            &grid,
+            &walkers,
+            &ghosts,
+            &mut buffer,
+            width,
+            height,
+        );
+    }
+    if config.compass_colors()
+        && let Some(previous) = previous
+    {
+        walk_bg::draw::compass_step(&mut buffer, width, height, &config, previous, walkers[0]);
+    }
+    crate::export::write_png(path, &buffer, width, height)?;
+
+    log::info!(
+        "Wrote {}x{} image after {} steps to {}",
+        width,
+        height,
+        config.get_preview_steps(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Steps shown by [`dry_run`]
+const DRY_RUN_STEPS: usize = 5;
+
+/// Log what the config resolves to and a few sample steps, without rendering
+pub fn dry_run(mut config: Config) {
+    let (width, height) = config.get_preview_size();
+    apply_spacing(&mut config, width, height);
+
+    let (grid_width, grid_height) = config.grid_size(width, height);
+    let mut grid = Grid::new(grid_width, grid_height);
+    grid.set_visit_increment(config.get_visit_increment());
+    grid.seed_from_pattern(config.get_seed_pattern());
+    let bounds = config.walk_bounds_in(grid_width, grid_height);
+
+    log::info!(
+        "Grid: {}x{} cells with {:?} pixels spacing on a {}x{} preview",
+        grid_width,
+        grid_height,
+        config.get_spacing(),
+        width,
+        height
+    );
+    log::info!("Walk bounds: {:?}", bounds);
+    log::info!(
+        "Walk interval: {:?} ({} algorithm)",
+        config.walk_interval(),
+        config.get_walk_algorithm()
+    );
+    log::info!(
+        "Colors: bg {:#010x}, fg {:#010x}, active {:#010x}",
+        config.get_bg_color(),
+        config.get_fg_color(),
+        config.get_active_color()
+    );
+
+    let mut pos = (bounds.0 + bounds.2 / 2, bounds.1 + bounds.3 / 2);
+    grid.visit(pos.0, pos.1);
+    let walk =
+        WalkAlgorithm::from_name(config.get_walk_algorithm()).unwrap_or(WalkAlgorithm::Uniform);
+    let mut rng = config.get_seed().map_or_else(Rng::new, Rng::from_seed);
+    let mut steps = vec![pos];
+    let mut last_dir = None;
+    for _ in 0..DRY_RUN_STEPS {
+        pos = walk.step(pos, &mut last_dir, bounds, &mut grid, &mut rng, &config);
+        grid.visit(pos.0, pos.1);
+        steps.push(pos);
+    }
+    log::info!("Sample steps: {:?}", steps);
+}
@@ -0,0 +1,96 @@
+//! The grid, walk and rendering logic behind walk_bg.
+//!
+//! The `walk_bg` binary is a Wayland front-end for this library. Embedders can
+//! drive the walk with [`utils::WalkAlgorithm`], keep track of visits in a
+//! [`Grid`] and render frames into their own buffers with [`render_frame`].
+
+pub mod draw;
+pub mod state;
+pub mod types;
+pub mod utils;
+
+pub use types::{Config, Grid};
+
+/// Render a complete frame of `grid` into `buffer`.
+///
+/// `buffer` holds `width * height` pixels in the configured
+/// [`draw::PixelFormat`], `Argb8888` by default. Every cell in `positions` is
+/// highlighted as an active field. `ghosts` are earlier positions, newest
+/// first, whose highlight fades with age.
+pub fn render_frame(
+    config: &Config,
+    grid: &Grid,
+    positions: &[(u32, u32)],
+    ghosts: &[(u32, u32)],
+    buffer: &mut [u8],
+    width: u32,
+    height: u32,
+) {
+    draw::draw_dot_grid(buffer, width, height, config, grid, positions, ghosts);
+    finish_frame(config, buffer, width, height);
+}
+
+/// Render only the given regions of a frame into `buffer`, which holds an
+/// earlier frame of the same size, as (x, y, width, height) in pixels.
+///
+/// The regions end up like in a frame from [`render_frame`], except for the
+/// glow, which needs the whole frame and is left out.
+#[allow(clippy::too_many_arguments)]
+pub fn render_regions(
+    config: &Config,
+    grid: &Grid,
+    positions: &[(u32, u32)],
+    ghosts: &[(u32, u32)],
+    buffer: &mut [u8],
+    width: u32,
+    height: u32,
+    regions: &[(u32, u32, u32, u32)],
+) {
+    for &region in regions {
+        draw::draw_dot_grid_region(
+            buffer, width, height, config, grid, positions, ghosts, region,
+        );
+    }
+    draw_border(config, buffer, width, height);
+}
+
+/// Render a frame that only shows the last positions of a walker, newest
+/// first, as set up by the `snake_length` option. Visit counts are ignored.
+pub fn render_snake(
+    config: &Config,
+    body: &[(u32, u32)],
+    buffer: &mut [u8],
+    width: u32,
+    height: u32,
+) {
+    draw::snake(buffer, width, height, config, body);
+    finish_frame(config, buffer, width, height);
+}
+
+/// Apply the effects that go on top of every rendered frame
+fn finish_frame(config: &Config, buffer: &mut [u8], width: u32, height: u32) {
+    if config.get_glow_radius() > 0 {
+        draw::glow(
+            buffer,
+            width,
+            height,
+            config.get_glow_radius(),
+            config.get_pixel_format().encode(config.get_bg_color()),
+        );
+    }
+
+    draw_border(config, buffer, width, height);
+}
+
+/// Paint the frame along the screen edges, if there is one
+fn draw_border(config: &Config, buffer: &mut [u8], width: u32, height: u32) {
+    if config.get_border_width() > 0 {
+        draw::border(
+            buffer,
+            width,
+            height,
+            config.get_border_width(),
+            config.get_pixel_format().encode(config.get_border_color()),
+        );
+    }
+}
@@ -1,25 +1,139 @@
+use rustix::event::{self, PollFd, PollFlags, Timespec};
+use signal_hook::{
+    consts::{SIGHUP, SIGINT, SIGTERM, SIGUSR1, SIGUSR2},
+    iterator::Signals,
+};
 use wayland_client::{Connection, globals::registry_queue_init};
 
-use types::{App, Config};
+use app::App;
+use config::load_config;
+use control::ControlSocket;
+use error::WalkBgError;
+use walk_bg::{Config, types::MAX_STEPS_PER_MINUTE};
+use watch::ConfigWatcher;
 
-pub mod draw;
-pub mod types;
-pub mod utils;
+mod app;
+mod buffer;
+mod config;
+mod control;
+mod error;
+mod export;
+mod headless;
+mod surface;
+mod watch;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Load config
-    let config_path = dirs::config_dir()
-        .unwrap_or_else(|| std::path::PathBuf::from("."))
-        .join("walk_bg")
-        .join("config.toml");
-    let res = std::fs::read_to_string(config_path);
-    let config = if let Ok(file) = res
-        && let Ok(cfg) = facet_toml::from_str(&file)
-    {
-        cfg
+/// Longest time the main loop sleeps, so Wayland events are handled promptly
+const MAX_SLEEP: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// Printed for `--help`
+const USAGE: &str = "\
+Usage: walk_bg [OPTIONS]
+       walk_bg validate [PATH]
+
+Options:
+  --config PATH            Load the config from PATH, - reads it from stdin
+  --walks-per-minute N     Override walks_per_minute from the config, ignoring bpm
+  --seed N                 Override seed from the config
+  --output-file PATH       Render a single PNG to PATH and exit
+  --dry-run                Log what the config resolves to and exit
+  -v, --verbose            Log debug messages
+  -h, --help               Print this message";
+
+/// Longest time to wait for the last frame before exiting after a limit
+const FINISH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Time between two writes of the `status_file`
+const STATUS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+fn main() -> std::process::ExitCode {
+    match run() {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("walk_bg: {e}");
+            std::process::ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<(), WalkBgError> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{USAGE}");
+        return Ok(());
+    }
+
+    // `walk_bg validate [path]` checks a config and exits
+    if args.first().is_some_and(|a| a == "validate") {
+        let path = args
+            .get(1)
+            .map_or_else(default_config_path, std::path::PathBuf::from);
+        let config = load_config(&path).map_err(WalkBgError::Config)?;
+        println!("OK");
+        println!("{config:#?}");
+        return Ok(());
+    }
+
+    // Load config, `--config -` reads it from stdin and leaves the path empty
+    let config_path = match args.iter().position(|a| a == "--config") {
+        Some(i) => match args.get(i + 1).map(String::as_str) {
+            Some("-") => None,
+            Some(path) => Some(std::path::PathBuf::from(path)),
+            None => return Err(WalkBgError::Usage("--config needs a path or -")),
+        },
+        None => Some(default_config_path()),
+    };
+    let overrides = Overrides::parse(&args)?;
+    let loaded = match &config_path {
+        Some(path) => load_config(path),
+        None => std::io::read_to_string(std::io::stdin())
+            .map_err(|e| e.to_string())
+            .and_then(|contents| config::parse_config(&contents, std::path::Path::new("."))),
+    };
+
+    // Set up logging, RUST_LOG takes precedence over --verbose and the config
+    let default_level = if args.iter().any(|a| a == "-v" || a == "--verbose") {
+        "debug"
     } else {
-        println!("Failed to parse config file, using defaults");
+        match &loaded {
+            Ok(config) => config.get_log_level(),
+            Err(_) => "info",
+        }
+    };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(default_level))
+        .init();
+
+    let mut config = loaded.unwrap_or_else(|e| {
+        log::warn!("Failed to parse config file, using defaults: {e}");
         Config::default()
+    });
+    overrides.apply(&mut config).map_err(WalkBgError::Config)?;
+
+    if args.iter().any(|a| a == "--dry-run") {
+        headless::dry_run(config);
+        return Ok(());
+    }
+
+    // Render a single image without connecting to Wayland
+    if let Some(i) = args.iter().position(|a| a == "--output-file") {
+        let path = args
+            .get(i + 1)
+            .ok_or(WalkBgError::Usage("--output-file needs a path"))?;
+        return headless::render_to_file(config, std::path::Path::new(path));
+    }
+
+    // SIGHUP and SIGUSR1 reload the config, SIGUSR2 takes a screenshot and
+    // SIGINT and SIGTERM shut down
+    let mut signals = Signals::new([SIGHUP, SIGUSR1, SIGUSR2, SIGINT, SIGTERM])?;
+
+    let mut watcher = watch_config(config_path.as_deref(), &config);
+
+    let control = if config.control_socket() {
+        ControlSocket::new(&control::socket_path())
+            .inspect_err(|e| log::warn!("Failed to create control socket: {e}"))
+            .ok()
+    } else {
+        None
     };
 
     // Connect to the Wayland server
@@ -29,51 +143,251 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let (globals, mut event_queue) = registry_queue_init(&conn)?;
     let qh = event_queue.handle();
 
-    let mut app = App::new(&globals, &qh);
+    let mut app = App::new(&globals, &qh)?;
     app.set_config(config);
 
-    app.create_surface(&qh, &globals);
+    // Learn about the outputs so the configured one can be picked
+    event_queue.roundtrip(&mut app)?;
+    app.create_surface(&qh, &globals)?;
 
     while !app.is_configured() {
         event_queue.blocking_dispatch(&mut app)?;
     }
 
-    let walk_interval =
-        std::time::Duration::from_secs_f32(60.0 / app.get_config().get_walks_per_minute());
+    let started = std::time::Instant::now();
+    let mut walk_interval = app.get_config().walk_interval();
     let mut last_walk = std::time::Instant::now();
+    let mut last_status: Option<std::time::Instant> = None;
+    // When a limit was reached, the loop only runs on until the last frame is shown
+    let mut finishing: Option<std::time::Instant> = None;
+    // Only flush when requests were queued since the last flush
+    let mut needs_flush = true;
 
     // Run the event loop
-    println!("Running background layer shell surface...");
+    log::info!("Running background layer shell surface...");
     loop {
-        if app.is_configured() && last_walk.elapsed() >= walk_interval {
-            // Perform a walk step
-            let (x, y) = app.get_current_pos();
-            let (new_x, new_y) = utils::random_walk_step(
-                x,
-                y,
-                app.get_grid().get_width(),
-                app.get_grid().get_height(),
-            );
+        let mut reload = false;
+        for signal in signals.pending() {
+            let name = signal_hook::low_level::signal_name(signal).unwrap_or("signal");
+            if signal == SIGINT || signal == SIGTERM {
+                log::info!("Received {name}, exiting");
+                app.save_state();
+                return Ok(());
+            }
+            if signal == SIGUSR2 {
+                let path = screenshot_path();
+                match app.export_png(&path) {
+                    Ok(()) => log::info!("Saved screenshot to {}", path.display()),
+                    Err(e) => log::error!("Failed to save screenshot: {e}"),
+                }
+                continue;
+            }
+            log::info!("Received {name}, reloading config");
+            reload = true;
+        }
+        if app.should_exit() {
+            log::info!("All layer surfaces were closed, exiting");
+            return Ok(());
+        }
+        if finishing.is_none()
+            && let Some(limit) = reached_limit(app.get_config(), started, app.get_total_steps())
+        {
+            log::info!("Reached {limit}, exiting");
+            finishing = Some(std::time::Instant::now());
+        }
+        if let Some(since) = finishing
+            && (!app.is_drawing() || since.elapsed() >= FINISH_TIMEOUT)
+        {
+            if let Some(path) = app.get_config().get_exit_screenshot() {
+                match app.export_png(std::path::Path::new(path)) {
+                    Ok(()) => log::info!("Saved screenshot to {path}"),
+                    Err(e) => log::error!("Failed to save screenshot: {e}"),
+                }
+            }
+            app.save_state();
+            event_queue.flush()?;
+            return Ok(());
+        }
+        if watcher.as_mut().is_some_and(|watcher| watcher.poll()) {
+            log::info!("Config file changed, reloading config");
+            reload = true;
+        }
+        if reload {
+            let loaded = config_path.as_deref().map(|path| -> Result<_, String> {
+                let mut config = load_config(path)?;
+                overrides.apply(&mut config)?;
+                Ok(config)
+            });
+            match loaded {
+                Some(Ok(config)) => {
+                    // Start or stop watching if `watch_config` was toggled
+                    if config.watch_config() != watcher.is_some() {
+                        watcher = watch_config(config_path.as_deref(), &config);
+                    }
+                    app.reload_config(config, &qh);
+                    walk_interval = app.get_config().walk_interval();
+                    needs_flush = true;
+                }
+                Some(Err(e)) => log::error!("Failed to reload config, keeping current one: {e}"),
+                None => log::warn!("Config was read from stdin, keeping current one"),
+            }
+        }
 
-            app.set_pos(new_x, new_y);
+        if let Some(control) = &control {
+            control.poll(|command| control::handle_command(&app, command));
+        }
+
+        if let Some(path) = app.get_config().get_status_file()
+            && last_status.is_none_or(|time| time.elapsed() >= STATUS_INTERVAL)
+        {
+            if let Err(e) = control::write_status(std::path::Path::new(path), &app) {
+                log::warn!("Failed to write status file {path}: {e}");
+            }
+            last_status = Some(std::time::Instant::now());
+        }
 
-            // Redraw
+        if finishing.is_none() && app.is_configured() && last_walk.elapsed() >= walk_interval {
+            // Perform a walk step
+            app.walk_step();
+            if let Some(pos) = app.get_current_pos() {
+                log::debug!("Walked to {:?}", pos);
+            }
+
+            // Redraw, or wait for the compositor to ask for the next frame.
+            // Animations between walk steps are drawn on its frame callbacks.
             app.draw(&qh);
 
             last_walk = std::time::Instant::now();
+            needs_flush = true;
         }
 
-        event_queue.flush()?;
-        match conn.prepare_read() {
-            Some(guard) => {
-                let _ = guard.read();
-                event_queue.dispatch_pending(&mut app)?;
-            }
-            None => {
-                event_queue.dispatch_pending(&mut app)?;
-            }
+        // Full repaints are rendered on a worker thread and shown once done
+        if app.present_pending(&qh) {
+            needs_flush = true;
+        }
+
+        // Handlers answer events with requests of their own, e.g. acking a configure
+        if event_queue.dispatch_pending(&mut app)? > 0 {
+            needs_flush = true;
+        }
+        if needs_flush {
+            event_queue.flush()?;
+            needs_flush = false;
+        }
+
+        // Wait for Wayland events, like frame callbacks, until the next step is due
+        let timeout = walk_interval.saturating_sub(last_walk.elapsed());
+        if let Some(guard) = conn.prepare_read()
+            && wait_readable(&guard.connection_fd(), timeout.min(MAX_SLEEP))
+        {
+            // Nothing to read after a spurious wakeup isn't an error
+            let _ = guard.read();
+        }
+        if event_queue.dispatch_pending(&mut app)? > 0 {
+            needs_flush = true;
+        }
+    }
+}
+
+/// Config fields given on the command line, they win over the config file
+struct Overrides {
+    walks_per_minute: Option<f32>,
+    seed: Option<u64>,
+}
+
+impl Overrides {
+    fn parse(args: &[String]) -> Result<Self, WalkBgError> {
+        let value = |flag: &str| {
+            args.iter()
+                .position(|a| a == flag)
+                .map(|i| args.get(i + 1).map(String::as_str))
+        };
+
+        let walks_per_minute = match value("--walks-per-minute") {
+            Some(value) => Some(
+                value
+                    .and_then(|value| value.parse::<f32>().ok())
+                    .filter(|&walks| walks > 0.0 && walks <= MAX_STEPS_PER_MINUTE)
+                    .ok_or(WalkBgError::Usage(
+                        "--walks-per-minute needs a number between 0 and 60000",
+                    ))?,
+            ),
+            None => None,
+        };
+        let seed = match value("--seed") {
+            Some(value) => Some(
+                value
+                    .and_then(|value| value.parse::<u64>().ok())
+                    .ok_or(WalkBgError::Usage("--seed needs a whole number"))?,
+            ),
+            None => None,
+        };
+
+        Ok(Self {
+            walks_per_minute,
+            seed,
+        })
+    }
+
+    /// Put the overrides on top of `config` and check the result again
+    fn apply(&self, config: &mut Config) -> Result<(), String> {
+        if let Some(walks_per_minute) = self.walks_per_minute {
+            config.set_walks_per_minute(walks_per_minute);
+        }
+        if let Some(seed) = self.seed {
+            config.set_seed(Some(seed));
         }
+        config.validate()
+    }
+}
 
-        std::thread::sleep(std::time::Duration::from_millis(10));
+/// Name of the limit in `config` that was reached, `None` if the walk goes on
+fn reached_limit(config: &Config, started: std::time::Instant, steps: u64) -> Option<&'static str> {
+    if config
+        .get_run_duration()
+        .is_some_and(|duration| started.elapsed() >= duration)
+    {
+        return Some("run_duration_secs");
+    }
+    if config.get_max_steps().is_some_and(|max| steps >= max) {
+        return Some("max_steps");
     }
+    None
+}
+
+/// Watch the config file at `path` if the config asks for it
+fn watch_config(path: Option<&std::path::Path>, config: &Config) -> Option<ConfigWatcher> {
+    match path {
+        Some(path) if config.watch_config() => ConfigWatcher::new(path)
+            .inspect_err(|e| log::warn!("Failed to watch config file: {e}"))
+            .ok(),
+        _ => None,
+    }
+}
+
+/// The config file used when `--config` isn't given
+fn default_config_path() -> std::path::PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| std::path::PathBuf::from("."))
+        .join("walk_bg")
+        .join("config.toml")
+}
+
+/// Where a screenshot taken now is saved, named after the current time
+fn screenshot_path() -> std::path::PathBuf {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    dirs::picture_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_else(|| std::path::PathBuf::from("."))
+        .join(format!("walk_bg-{secs}.png"))
+}
+
+/// Wait until `fd` is readable or `timeout` passed, `false` on timeout
+fn wait_readable(fd: &impl std::os::fd::AsFd, timeout: std::time::Duration) -> bool {
+    let mut fds = [PollFd::new(fd, PollFlags::IN)];
+    let timeout = Timespec::try_from(timeout).ok();
+    // An interrupted poll counts as a timeout, the loop checks everything again
+    event::poll(&mut fds, timeout.as_ref()).is_ok_and(|ready| ready > 0)
 }
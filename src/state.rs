@@ -0,0 +1,142 @@
+//! Saving and restoring the walk between runs
+
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use crate::types::Grid;
+use crate::utils::Rng;
+
+/// Identifies walk_bg state files
+const MAGIC: &[u8; 4] = b"WBGS";
+
+/// Version of the state file format, bump it whenever the layout changes
+pub const VERSION: u32 = 2;
+
+/// Everything needed to continue a walk where it left off
+pub struct SavedState {
+    pub grid: Grid,
+    pub positions: Vec<(u32, u32)>,
+    /// The random number generator, so the walk takes the same steps it
+    /// would have taken without the restart
+    pub rng: Rng,
+}
+
+impl SavedState {
+    /// Write the state to `path`, replacing the previous file atomically
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut data = Vec::with_capacity(self.grid.get_visits_slice().len() + 32);
+        data.extend_from_slice(MAGIC);
+        for value in [
+            VERSION,
+            self.grid.get_width(),
+            self.grid.get_height(),
+            self.positions.len() as u32,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&self.rng.state().to_le_bytes());
+        for &(x, y) in &self.positions {
+            data.extend_from_slice(&x.to_le_bytes());
+            data.extend_from_slice(&y.to_le_bytes());
+        }
+        data.extend_from_slice(self.grid.get_visits_slice());
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp_path = path.with_extension("tmp");
+        std::fs::File::create(&tmp_path)?.write_all(&data)?;
+        std::fs::rename(tmp_path, path)
+    }
+
+    /// Read a state file written by [`SavedState::save`]
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut file = std::fs::File::open(path)?;
+
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a walk_bg state file"));
+        }
+
+        let version = read_u32(&mut file)?;
+        if version != VERSION {
+            return Err(invalid(&format!(
+                "unsupported state file version {version}, expected {VERSION}"
+            )));
+        }
+
+        let width = read_u32(&mut file)?;
+        let height = read_u32(&mut file)?;
+        let count = read_u32(&mut file)?;
+        let mut rng_state = [0u8; 8];
+        file.read_exact(&mut rng_state)?;
+        let rng = Rng::from_state(u64::from_le_bytes(rng_state));
+        let positions = (0..count)
+            .map(|_| Ok((read_u32(&mut file)?, read_u32(&mut file)?)))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut visits = Vec::new();
+        file.read_to_end(&mut visits)?;
+        let grid = Grid::from_visits(width, height, visits)
+            .ok_or_else(|| invalid("grid size doesn't match the visit counts"))?;
+
+        Ok(SavedState {
+            grid,
+            positions,
+            rng,
+        })
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state");
+        let mut grid = Grid::new(3, 2);
+        grid.visit(1, 1);
+        let mut rng = Rng::from_seed(7);
+        rng.next_u64();
+        SavedState {
+            grid,
+            positions: vec![(1, 1), (2, 0)],
+            rng: rng.clone(),
+        }
+        .save(&path)
+        .unwrap();
+
+        let mut state = SavedState::load(&path).unwrap();
+        assert_eq!(state.grid.get_visits_slice(), &[0, 0, 0, 0, 1, 0]);
+        assert_eq!(state.positions, [(1, 1), (2, 0)]);
+        assert_eq!(state.rng.next_u64(), rng.next_u64());
+    }
+
+    #[test]
+    fn oversized_grid_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state");
+        let mut data = MAGIC.to_vec();
+        for value in [VERSION, u32::MAX, u32::MAX, 0, 0, 0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.push(1);
+        std::fs::write(&path, data).unwrap();
+
+        let error = SavedState::load(&path).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
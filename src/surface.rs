@@ -0,0 +1,1270 @@
+//! A background layer surface on one output and the walk shown on it
+
+use std::borrow::Cow;
+
+use smithay_client_toolkit::{
+    shell::{
+        WaylandSurface,
+        wlr_layer::{Anchor, Layer, LayerSurface},
+    },
+    shm::Shm,
+};
+use walk_bg::{
+    draw::{PixelFormat, Rotation},
+    state::SavedState,
+    types::{Config, Edge, Grid, LayerKind, ResizePolicy, StartPosition},
+    utils::{self, Rng, WalkAlgorithm},
+};
+use wayland_client::{
+    QueueHandle,
+    protocol::{wl_output, wl_shm, wl_surface},
+};
+
+use crate::app::App;
+use crate::buffer::ShmBuffers;
+use crate::error::WalkBgError;
+
+/// Where the walk is saved between runs
+fn state_path() -> Option<std::path::PathBuf> {
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .map(|dir| dir.join("walk_bg").join("state"))
+}
+
+/// Map an output transform to a buffer rotation, `None` for flipped transforms
+fn rotation(transform: wl_output::Transform) -> Option<Rotation> {
+    match transform {
+        wl_output::Transform::Normal => Some(Rotation::Normal),
+        wl_output::Transform::_90 => Some(Rotation::Rotate90),
+        wl_output::Transform::_180 => Some(Rotation::Rotate180),
+        wl_output::Transform::_270 => Some(Rotation::Rotate270),
+        _ => None,
+    }
+}
+
+/// The `wl_shm` format of a pixel format, every compositor supports both
+fn shm_format(format: PixelFormat) -> wl_shm::Format {
+    match format {
+        PixelFormat::Argb8888 => wl_shm::Format::Argb8888,
+        PixelFormat::Xrgb8888 => wl_shm::Format::Xrgb8888,
+    }
+}
+
+/// The layer shell layer of a configured layer
+pub fn shell_layer(kind: LayerKind) -> Layer {
+    match kind {
+        LayerKind::Background => Layer::Background,
+        LayerKind::Bottom => Layer::Bottom,
+        LayerKind::Top => Layer::Top,
+        LayerKind::Overlay => Layer::Overlay,
+    }
+}
+
+/// Anchor the layer surface, size it and set its exclusive zone as configured
+pub fn place(layer_surface: &LayerSurface, config: &Config) {
+    let anchor = config
+        .get_anchor()
+        .into_iter()
+        .fold(Anchor::empty(), |anchor, edge| {
+            anchor
+                | match edge {
+                    Edge::Top => Anchor::TOP,
+                    Edge::Bottom => Anchor::BOTTOM,
+                    Edge::Left => Anchor::LEFT,
+                    Edge::Right => Anchor::RIGHT,
+                }
+        });
+    layer_surface.set_anchor(anchor);
+    let (width, height) = config.get_surface_size();
+    layer_surface.set_size(width, height);
+    layer_surface.set_exclusive_zone(config.get_exclusive_zone());
+}
+
+/// How long a ripple takes to fade out
+const RIPPLE_DURATION: std::time::Duration = std::time::Duration::from_millis(1500);
+
+/// Radius of a ripple when it has faded out, in dot spacings
+const RIPPLE_RADIUS: f32 = 3.0;
+
+/// Draw every ripple as a ring that grows and fades with its age
+fn draw_ripples(
+    frame: &mut [u8],
+    width: u32,
+    height: u32,
+    config: &Config,
+    ripples: &[((u32, u32), std::time::Instant)],
+) {
+    let (spacing_x, spacing_y) = config.get_spacing();
+    for &((x, y), started) in ripples {
+        let progress = started.elapsed().as_secs_f32() / RIPPLE_DURATION.as_secs_f32();
+        let radius = progress * RIPPLE_RADIUS * spacing_x.min(spacing_y) as f32;
+        let alpha = (1.0 - progress).max(0.0) * config.get_dot_alpha() as f32;
+        walk_bg::draw::circle(
+            frame,
+            width,
+            height,
+            config.get_pixel_format(),
+            ((x * spacing_x) as i32, (y * spacing_y) as i32),
+            radius as i32,
+            config.get_active_color(),
+            alpha as u8,
+        );
+    }
+}
+
+/// Everything a frame is rendered from. Owned copies can be sent to the
+/// worker thread, frames on the main thread borrow from the surface.
+struct FrameInput<'a> {
+    config: Cow<'a, Config>,
+    grid: Cow<'a, Grid>,
+    walkers: Cow<'a, [(u32, u32)]>,
+    ghosts: Cow<'a, [(u32, u32)]>,
+    snake: Cow<'a, [(u32, u32)]>,
+    previous_pos: Option<(u32, u32)>,
+    ripples: Cow<'a, [((u32, u32), std::time::Instant)]>,
+    /// Opacity of the startup fade-in, `None` once it is done
+    fade_alpha: Option<f32>,
+    /// Position of the sliding active dot in grid cells, `None` if it sits on
+    /// its cell
+    glide: Option<(f32, f32)>,
+    width: u32,
+    height: u32,
+    rotation: Rotation,
+}
+
+impl FrameInput<'_> {
+    /// What the frame shows of the walk, to compare the next frame with.
+    /// `None` if ripples or the fade-in cover more than the cells, then the
+    /// next frame has to be drawn completely.
+    fn snapshot(&self) -> Option<DrawnFrame> {
+        if !self.ripples.is_empty() || self.fade_alpha.is_some() {
+            return None;
+        }
+        Some(DrawnFrame {
+            visits: self.grid.get_visits_slice().to_vec(),
+            walkers: self.walkers.to_vec(),
+            ghosts: self.ghosts.to_vec(),
+            glide_cells: self.glide_cells(),
+        })
+    }
+
+    /// The cells the active dot slides between, it lies within their regions
+    fn glide_cells(&self) -> Vec<(u32, u32)> {
+        match (self.glide, self.previous_pos) {
+            (Some(_), Some(previous)) => vec![previous, self.walkers[0]],
+            _ => Vec::new(),
+        }
+    }
+
+    /// The walkers that are highlighted in their cell, the sliding one is
+    /// drawn on its own
+    fn highlighted(&self) -> &[(u32, u32)] {
+        if self.glide.is_some() {
+            &self.walkers[1..]
+        } else {
+            &self.walkers
+        }
+    }
+
+    /// Render only `regions` into `buffer`, which holds the previous frame
+    fn render_regions(&self, regions: &[(u32, u32, u32, u32)], buffer: &mut [u8]) {
+        walk_bg::render_regions(
+            &self.config,
+            &self.grid,
+            self.highlighted(),
+            &self.ghosts,
+            buffer,
+            self.width,
+            self.height,
+            regions,
+        );
+        // The regions cover the cells the dot slides between
+        if let Some(position) = self.glide {
+            walk_bg::draw::active_marker(buffer, self.width, self.height, &self.config, position);
+        }
+    }
+
+    /// Render the frame into `buffer` in the orientation of the output.
+    /// Rotated frames are rendered upright into the scratch buffer first,
+    /// supersampled frames at the larger size before they are shrunk.
+    fn render(&self, scratch: &mut Scratch, buffer: &mut [u8]) {
+        let (width, height) = (self.width, self.height);
+        let frame = if self.rotation == Rotation::Normal {
+            &mut buffer[..]
+        } else {
+            scratch.upright.resize((width * 4 * height) as usize, 0);
+            &mut scratch.upright[..]
+        };
+
+        let factor = self.config.get_supersample();
+        if factor > 1 {
+            let mut config = self.config.as_ref().clone();
+            config.scale_lengths(factor);
+            let (large_width, large_height) = (width * factor, height * factor);
+            let large = &mut scratch.supersampled;
+            large.resize((large_width * 4 * large_height) as usize, 0);
+            self.render_upright(&config, large, large_width, large_height);
+            walk_bg::draw::downsample(large, frame, width, height, factor);
+        } else {
+            self.render_upright(&self.config, frame, width, height);
+        }
+
+        if self.rotation != Rotation::Normal {
+            walk_bg::draw::rotate(&scratch.upright, buffer, width, height, self.rotation);
+        }
+    }
+
+    /// Render the upright frame at the given size, with lengths in `config`
+    /// matching it
+    fn render_upright(&self, config: &Config, frame: &mut [u8], width: u32, height: u32) {
+        if config.get_snake_length().is_some() {
+            walk_bg::render_snake(config, &self.snake, frame, width, height);
+        } else {
+            walk_bg::render_frame(
+                config,
+                &self.grid,
+                self.highlighted(),
+                &self.ghosts,
+                frame,
+                width,
+                height,
+            );
+            if let Some(position) = self.glide {
+                walk_bg::draw::active_marker(frame, width, height, config, position);
+            }
+        }
+        if config.compass_colors()
+            && let Some(previous) = self.previous_pos
+        {
+            walk_bg::draw::compass_step(frame, width, height, config, previous, self.walkers[0]);
+        }
+
+        if !self.ripples.is_empty() {
+            draw_ripples(frame, width, height, config, &self.ripples);
+        }
+
+        if let Some(alpha) = self.fade_alpha {
+            walk_bg::draw::fade(frame, alpha);
+        }
+    }
+}
+
+/// Buffers reused from frame to frame
+#[derive(Default)]
+struct Scratch {
+    /// Frame before it is rotated into the buffer
+    upright: Vec<u8>,
+    /// Frame at the `supersample` size before it is shrunk
+    supersampled: Vec<u8>,
+}
+
+/// The walk as shown by a frame, so the next frame only needs to redraw the
+/// cells that changed since
+#[derive(Clone)]
+struct DrawnFrame {
+    visits: Vec<u8>,
+    walkers: Vec<(u32, u32)>,
+    ghosts: Vec<(u32, u32)>,
+    /// Cells the active dot was sliding between, if it was
+    glide_cells: Vec<(u32, u32)>,
+}
+
+/// A frame rendered on the worker thread and the buffer size it is for
+struct PendingFrame {
+    receiver: std::sync::mpsc::Receiver<Vec<u8>>,
+    width: u32,
+    height: u32,
+    rotation: Rotation,
+    drawn: Option<DrawnFrame>,
+    /// Whether a draw was skipped while rendering, so the frame is outdated
+    redraw: bool,
+}
+
+/// What a surface needs to know about its output when it is configured
+pub struct OutputInfo {
+    /// Size of the output the surface is on, used when the compositor leaves
+    /// the size to us
+    pub size: Option<(u32, u32)>,
+    /// Largest width and height among all outputs, sizes beyond it are capped
+    pub largest: Option<(u32, u32)>,
+    pub has_pointer: bool,
+}
+
+/// A background layer surface on one output, with its own buffer and walk
+pub struct OutputSurface {
+    layer_surface: LayerSurface,
+    /// Output the surface was created for, `None` if the compositor chose
+    output: Option<wl_output::WlOutput>,
+    /// Output the surface is shown on
+    current_output: Option<wl_output::WlOutput>,
+    /// The app's config as it was given
+    base_config: Config,
+    /// The app's config, with the lengths scaled and the spacing fitted to
+    /// this surface
+    config: Config,
+    /// Size of the last configure, 0 if the compositor left it to us
+    requested_size: (u32, u32),
+    /// Buffer pixels per surface pixel
+    scale: u32,
+    /// Size of the buffer, the surface size times the scale
+    width: u32,
+    height: u32,
+    configured: bool,
+    buffers: ShmBuffers<DrawnFrame>,
+    transform: wl_output::Transform,
+    scratch: Scratch,
+    /// Frame being rendered on the worker thread, if any
+    pending_frame: Option<PendingFrame>,
+    /// Whether the compositor hasn't asked for the next frame yet
+    awaiting_frame: bool,
+    /// Whether a draw was held back until the compositor asks for a frame
+    needs_draw: bool,
+    /// What the last presented frame shows, `None` if the next one has to be
+    /// damaged completely
+    presented: Option<DrawnFrame>,
+    grid: Grid,
+    /// Positions of all walkers. The first one is the main walker, it is
+    /// never retired and the only one with ghosts and compass colors
+    walkers: Vec<(u32, u32)>,
+    /// Direction of each walker's last random move, for `momentum`
+    last_dirs: Vec<Option<(i64, i64)>>,
+    /// Position before the last walk step, for the compass colors
+    previous_pos: Option<(u32, u32)>,
+    /// Recent positions for the fading active highlight, newest first
+    ghosts: Vec<(u32, u32)>,
+    /// Last positions of the main walker drawn with `snake_length`, newest
+    /// first
+    snake: std::collections::VecDeque<(u32, u32)>,
+    fade_start: Option<std::time::Instant>,
+    /// When the walkers last moved, for `smooth_steps`
+    last_step: std::time::Instant,
+    /// Cells ripples spread from and when they started
+    ripples: Vec<((u32, u32), std::time::Instant)>,
+    awaiting_cursor: bool,
+    last_decay: std::time::Instant,
+    frame_times: std::collections::VecDeque<std::time::Instant>,
+}
+
+impl OutputSurface {
+    pub fn new(
+        layer_surface: LayerSurface,
+        output: Option<wl_output::WlOutput>,
+        config: Config,
+    ) -> Result<Self, WalkBgError> {
+        let mut grid = Grid::new(0, 0);
+        grid.set_visit_increment(config.get_visit_increment());
+
+        Ok(Self {
+            layer_surface,
+            // Best guess until the surface enters an output
+            current_output: output.clone(),
+            output,
+            base_config: config.clone(),
+            config,
+            requested_size: (0, 0),
+            scale: 1,
+            width: 0,
+            height: 0,
+            configured: false,
+            buffers: ShmBuffers::new()?,
+            transform: wl_output::Transform::Normal,
+            scratch: Scratch::default(),
+            pending_frame: None,
+            awaiting_frame: false,
+            needs_draw: false,
+            presented: None,
+            grid,
+            walkers: vec![(0, 0)],
+            last_dirs: Vec::new(),
+            previous_pos: None,
+            ghosts: Vec::new(),
+            snake: std::collections::VecDeque::new(),
+            fade_start: None,
+            last_step: std::time::Instant::now(),
+            ripples: Vec::new(),
+            awaiting_cursor: false,
+            last_decay: std::time::Instant::now(),
+            frame_times: std::collections::VecDeque::new(),
+        })
+    }
+
+    pub fn wl_surface(&self) -> &wl_surface::WlSurface {
+        self.layer_surface.wl_surface()
+    }
+
+    /// Output the surface was created for, `None` if the compositor chose
+    pub fn output(&self) -> Option<&wl_output::WlOutput> {
+        self.output.as_ref()
+    }
+
+    /// Output the surface is shown on, or the one it was created for until
+    /// that is known
+    pub fn current_output(&self) -> Option<&wl_output::WlOutput> {
+        self.current_output.as_ref().or(self.output.as_ref())
+    }
+
+    pub fn enter(&mut self, output: &wl_output::WlOutput) {
+        self.current_output = Some(output.clone());
+    }
+
+    pub fn leave(&mut self, output: &wl_output::WlOutput) {
+        if self.current_output.as_ref() == Some(output) {
+            self.current_output = None;
+        }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.configured
+    }
+
+    /// Whether a frame is still being rendered or waits to be drawn
+    pub fn is_drawing(&self) -> bool {
+        self.pending_frame.is_some() || self.needs_draw
+    }
+
+    /// Position of the main walker
+    pub fn get_current_pos(&self) -> (u32, u32) {
+        self.walkers[0]
+    }
+
+    /// Positions of all walkers, the main walker first
+    pub fn get_positions(&self) -> &[(u32, u32)] {
+        &self.walkers
+    }
+
+    pub fn get_grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    /// Frames drawn within the last second
+    pub fn fps(&self) -> f32 {
+        let second_ago = std::time::Instant::now() - std::time::Duration::from_secs(1);
+        self.frame_times
+            .iter()
+            .filter(|&&time| time > second_ago)
+            .count() as f32
+    }
+
+    /// Size of the last configure, 0 if the compositor left it to us
+    pub fn requested_size(&self) -> (u32, u32) {
+        self.requested_size
+    }
+
+    /// Draw with `scale` buffer pixels per surface pixel. Returns `false` if
+    /// the scale didn't change.
+    ///
+    /// The surface has to be configured again to resize the buffer.
+    pub fn set_scale(&mut self, scale: u32) -> bool {
+        if scale == self.scale {
+            return false;
+        }
+        log::info!("Output scale changed to {scale}");
+        self.scale = scale;
+        self.layer_surface
+            .wl_surface()
+            .set_buffer_scale(scale as i32);
+        true
+    }
+
+    /// Apply a new config at runtime, resizing the grid if the spacing changed
+    pub fn reload(&mut self, config: Config) {
+        let old_spacing = self.config.get_spacing();
+        self.grid.set_visit_increment(config.get_visit_increment());
+        if config.get_layer() != self.config.get_layer() {
+            // Takes effect with the next commit
+            self.layer_surface
+                .set_layer(shell_layer(config.get_layer()));
+        }
+        let placement = |config: &Config| {
+            (
+                config.get_anchor(),
+                config.get_surface_size(),
+                config.get_exclusive_zone(),
+            )
+        };
+        if placement(&config) != placement(&self.config) {
+            // Answered with a configure for the new size
+            place(&self.layer_surface, &config);
+        }
+        self.base_config = config.clone();
+        self.config = config;
+
+        if !self.configured {
+            return;
+        }
+
+        self.fit_config();
+        if self.config.get_spacing() != old_spacing {
+            let (grid_width, grid_height) = self.grid_size();
+            let policy = self.config.get_resize_policy();
+            self.grid.resize(grid_width, grid_height, policy);
+            if policy == ResizePolicy::Clear {
+                self.grid.seed_from_pattern(self.config.get_seed_pattern());
+            }
+            log::info!("Grid resized: {}x{}", grid_width, grid_height);
+        }
+
+        self.check_walk_bounds();
+        self.keep_walkers_in_bounds();
+
+        self.draw_offloaded();
+    }
+
+    /// Set up the buffer and grid for the size the compositor asked for.
+    ///
+    /// Only the `primary` surface continues a saved walk.
+    pub fn configure(
+        &mut self,
+        size: (u32, u32),
+        output: OutputInfo,
+        primary: bool,
+        walk: WalkAlgorithm,
+        rng: &mut Rng,
+    ) {
+        self.requested_size = size;
+        (self.width, self.height) = size;
+
+        // The compositor leaves the size to us, so fill the whole output.
+        // Modes are given in buffer pixels.
+        if self.width == 0 || self.height == 0 {
+            let (width, height) = output.size.unwrap_or_else(|| {
+                log::warn!("Output size is unknown, assuming 1920x1080");
+                (1920, 1080)
+            });
+            (self.width, self.height) = (width / self.scale, height / self.scale);
+        }
+
+        // A bogus size would otherwise allocate a huge buffer
+        let size = (self.width, self.height);
+        let ceiling = self.config.get_max_surface_size();
+        let clamped = utils::clamp_surface_size(size, output.largest, ceiling);
+        if clamped != size {
+            log::warn!(
+                "Configured size {}x{} is larger than any output, capping it to {}x{}",
+                size.0,
+                size.1,
+                clamped.0,
+                clamped.1
+            );
+            (self.width, self.height) = clamped;
+        }
+
+        // Buffers are a whole multiple of the surface size
+        self.width *= self.scale;
+        self.height *= self.scale;
+        log::info!(
+            "Display size: {}x{} at scale {}",
+            self.width,
+            self.height,
+            self.scale
+        );
+
+        self.fit_config();
+        let (grid_width, grid_height) = self.grid_size();
+        // The first configure always starts with a fresh grid
+        let policy = if self.configured {
+            self.config.get_resize_policy()
+        } else {
+            ResizePolicy::Clear
+        };
+        self.grid.resize(grid_width, grid_height, policy);
+        if policy == ResizePolicy::Clear {
+            self.grid.seed_from_pattern(self.config.get_seed_pattern());
+        }
+        self.check_walk_bounds();
+        if policy == ResizePolicy::Clear {
+            // A fresh grid starts over with the walkers around the start
+            let positions = utils::start_positions(
+                self.start_pos(),
+                self.config.get_walker_count(),
+                self.walk_bounds(),
+            );
+            self.walkers.truncate(1);
+            self.set_pos(positions[0].0, positions[0].1);
+            for &(x, y) in &positions[1..] {
+                self.grid.visit(x, y);
+                self.walkers.push((x, y));
+            }
+        } else {
+            self.keep_walkers_in_bounds();
+        }
+
+        let restored =
+            !self.configured && primary && self.config.persist_state() && self.restore_state(rng);
+
+        if policy == ResizePolicy::Clear && !restored {
+            for _ in 0..self.config.get_warmup_steps() {
+                self.advance(walk, rng);
+            }
+        }
+
+        if !self.configured && !restored && self.config.get_start() == StartPosition::Cursor {
+            if !self.config.interactive() {
+                log::info!("Starting at the cursor needs interactive, starting at the center");
+            } else if output.has_pointer {
+                self.awaiting_cursor = true;
+            } else {
+                log::info!("No pointer available, starting at the center");
+            }
+        }
+
+        log::info!(
+            "Grid initialized: {}x{} (start: {:?})",
+            grid_width,
+            grid_height,
+            self.walkers[0]
+        );
+
+        if !self.configured && self.config.get_fade_in_secs() > 0.0 {
+            self.fade_start = Some(std::time::Instant::now());
+        }
+        self.configured = true;
+
+        self.draw_offloaded();
+    }
+
+    /// Render in the output's orientation so the compositor doesn't have to
+    /// rotate
+    pub fn set_transform(&mut self, transform: wl_output::Transform) {
+        self.transform = if rotation(transform).is_some() {
+            transform
+        } else {
+            log::debug!("Unsupported output transform {transform:?}, using normal");
+            wl_output::Transform::Normal
+        };
+        self.layer_surface
+            .wl_surface()
+            .set_buffer_transform(self.transform);
+        self.draw_offloaded();
+    }
+
+    /// The part of the grid the walk is confined to as (x, y, width, height).
+    ///
+    /// Falls back to the full grid if no bounds are configured or they don't fit.
+    fn walk_bounds(&self) -> (u32, u32, u32, u32) {
+        self.config
+            .walk_bounds_in(self.grid.get_width(), self.grid.get_height())
+    }
+
+    /// Warn if the configured walk bounds don't fit the current grid, and
+    /// fall back to every direction if the allowed ones can't move in them
+    fn check_walk_bounds(&mut self) {
+        if let Some(bounds) = self.config.get_walk_bounds()
+            && self.walk_bounds() != bounds
+        {
+            log::warn!(
+                "walk_bounds {:?} exceed the {}x{} grid, using the full grid",
+                bounds,
+                self.grid.get_width(),
+                self.grid.get_height()
+            );
+        }
+
+        let (_, _, w, h) = self.walk_bounds();
+        if let Err(e) = self.config.check_directions(w, h) {
+            log::error!("{e}, moving in every direction instead");
+            self.config.clear_allowed_directions();
+        }
+    }
+
+    /// Retire walkers that ended up outside the walk bounds, the main walker
+    /// goes back to the start instead
+    fn keep_walkers_in_bounds(&mut self) {
+        let (x, y, w, h) = self.walk_bounds();
+        let inside = |&(pos_x, pos_y): &(u32, u32)| {
+            (x..x + w).contains(&pos_x) && (y..y + h).contains(&pos_y)
+        };
+
+        if !inside(&self.walkers[0]) {
+            let (start_x, start_y) = self.start_pos();
+            self.set_pos(start_x, start_y);
+        }
+        self.walkers.retain(inside);
+    }
+
+    /// Where the walk starts: the center of the walk bounds
+    fn start_pos(&self) -> (u32, u32) {
+        let (x, y, w, h) = self.walk_bounds();
+        (x + w / 2, y + h / 2)
+    }
+
+    /// Move the walker to the cell closest to the pointer at `position` in
+    /// surface coordinates, if the walk is still waiting for it
+    pub fn start_at_cursor(&mut self, position: (f64, f64), shm: &Shm, qh: &QueueHandle<App>) {
+        if !self.awaiting_cursor {
+            return;
+        }
+        self.awaiting_cursor = false;
+
+        // The pointer is in surface pixels, the spacing in buffer pixels
+        let position = (
+            position.0 * self.scale as f64,
+            position.1 * self.scale as f64,
+        );
+        let (spacing_x, spacing_y) = self.config.get_spacing();
+        let (x, y, w, h) = self.walk_bounds();
+        let cell_x = ((position.0 / spacing_x as f64).round().max(0.0) as u32).clamp(x, x + w - 1);
+        let cell_y = ((position.1 / spacing_y as f64).round().max(0.0) as u32).clamp(y, y + h - 1);
+
+        log::info!("Starting the walk at the pointer ({cell_x}, {cell_y})");
+        self.set_pos(cell_x, cell_y);
+        self.draw(shm, qh);
+    }
+
+    /// Derive the config of this surface from the app's, with the lengths
+    /// scaled to buffer pixels and the spacing fitted to the buffer size
+    fn fit_config(&mut self) {
+        self.config = self.base_config.clone();
+        self.config.scale_lengths(self.scale);
+        self.apply_spacing();
+    }
+
+    /// Derive `pixels_per_point` from `target_dots` and the surface size, if
+    /// set, and widen it if the grid would exceed `max_grid_cells`
+    fn apply_spacing(&mut self) {
+        if let Some(target_dots) = self.config.get_target_dots() {
+            let spacing = self.config.spacing_for(self.width, self.height);
+            self.config.set_pixels_per_point(spacing);
+            log::info!(
+                "Using {} pixels per point for roughly {} dots",
+                self.config.get_pixels_per_point(),
+                target_dots
+            );
+        }
+
+        if self.config.fit_max_grid_cells(self.width, self.height) {
+            log::warn!(
+                "Grid would exceed max_grid_cells, using a spacing of {:?} instead",
+                self.config.get_spacing()
+            );
+        }
+    }
+
+    /// Grid dimensions for the current surface size and spacing
+    fn grid_size(&self) -> (u32, u32) {
+        self.config.grid_size(self.width, self.height)
+    }
+
+    /// Save the walk so the next start can continue it
+    pub fn save_state(&self, rng: &Rng) {
+        if !self.configured || !self.config.persist_state() {
+            return;
+        }
+        let Some(path) = state_path() else {
+            log::warn!("No state directory found, not saving the walk");
+            return;
+        };
+
+        let state = SavedState {
+            grid: self.grid.clone(),
+            positions: self.walkers.clone(),
+            rng: rng.clone(),
+        };
+        match state.save(&path) {
+            Ok(()) => log::info!("Saved walk to {}", path.display()),
+            Err(e) => log::error!("Failed to save walk to {}: {e}", path.display()),
+        }
+    }
+
+    /// Write the frame the compositor was given last to a PNG at `path`,
+    /// turned upright
+    pub fn export_png(&self, path: &std::path::Path) -> Result<(), WalkBgError> {
+        let buffer = self
+            .buffers
+            .attached_data()
+            .ok_or(WalkBgError::NothingDrawn)?;
+        let rotation = rotation(self.transform).unwrap_or(Rotation::Normal);
+        if rotation == Rotation::Normal {
+            return crate::export::write_png(path, buffer, self.width, self.height);
+        }
+
+        let (buffer_width, buffer_height) = rotation.buffer_size(self.width, self.height);
+        let mut upright = vec![0u8; buffer.len()];
+        walk_bg::draw::rotate(
+            buffer,
+            &mut upright,
+            buffer_width,
+            buffer_height,
+            rotation.inverse(),
+        );
+        crate::export::write_png(path, &upright, self.width, self.height)
+    }
+
+    /// Continue a saved walk if it was saved for a grid of the same size
+    fn restore_state(&mut self, rng: &mut Rng) -> bool {
+        let Some(path) = state_path() else {
+            return false;
+        };
+
+        let state = match SavedState::load(&path) {
+            Ok(state) => state,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return false,
+            Err(e) => {
+                log::warn!("Failed to restore walk from {}: {e}", path.display());
+                return false;
+            }
+        };
+
+        if state.grid.get_width() != self.grid.get_width()
+            || state.grid.get_height() != self.grid.get_height()
+        {
+            log::info!("Saved walk is for a different grid size, starting a new one");
+            return false;
+        }
+
+        self.grid = state.grid;
+        self.grid
+            .set_visit_increment(self.config.get_visit_increment());
+        let (x, y, w, h) = self.walk_bounds();
+        let positions: Vec<_> = state
+            .positions
+            .into_iter()
+            .filter(|&(pos_x, pos_y)| (x..x + w).contains(&pos_x) && (y..y + h).contains(&pos_y))
+            .collect();
+        if !positions.is_empty() {
+            self.walkers = positions;
+            self.previous_pos = None;
+            self.snake.clear();
+        }
+        *rng = state.rng;
+        log::info!("Restored walk from {}", path.display());
+        true
+    }
+
+    /// Advance the walk by one step that is shown, sending out ripples from
+    /// the walkers
+    pub fn walk_step(&mut self, walk: WalkAlgorithm, rng: &mut Rng) {
+        self.advance(walk, rng);
+        if self.config.ripple() {
+            let now = std::time::Instant::now();
+            self.ripples
+                .extend(self.walkers.iter().map(|&walker| (walker, now)));
+        }
+    }
+
+    /// Advance the walk by one step using the configured algorithm, without
+    /// any effects that only matter on screen
+    fn advance(&mut self, walk: WalkAlgorithm, rng: &mut Rng) {
+        if self.awaiting_cursor {
+            log::info!("Pointer isn't over the background, starting at the center");
+            self.awaiting_cursor = false;
+        }
+
+        let bounds = self.walk_bounds();
+        let previous = self.walkers[0];
+        // Walkers come and go at the end, so their directions stay in line
+        self.last_dirs.resize(self.walkers.len(), None);
+        for walker in 0..self.walkers.len() {
+            let (x, y) = walk.step(
+                self.walkers[walker],
+                &mut self.last_dirs[walker],
+                bounds,
+                &mut self.grid,
+                rng,
+                &self.config,
+            );
+            self.walkers[walker] = (x, y);
+            self.grid.visit(x, y);
+        }
+        utils::update_population(&mut self.walkers, bounds, &mut self.grid, rng, &self.config);
+        self.previous_pos = Some(previous);
+        self.last_step = std::time::Instant::now();
+        self.ghosts.insert(0, previous);
+        self.ghosts.truncate(self.config.get_ghost_steps() as usize);
+        self.grow_snake();
+
+        if let Some(max_age) = self.config.get_trail_max_age() {
+            self.grid.forget_older_than(max_age);
+        }
+
+        // Catch up on every fade that was due since the last step
+        if let Some(interval) = self.config.get_decay_interval() {
+            let fades = (self.last_decay.elapsed().as_secs_f64() / interval.as_secs_f64()) as u32;
+            // Every cell is faded out long before a count could reach 0 again
+            for _ in 0..fades.min(u8::MAX as u32) {
+                self.grid.decay(
+                    self.config.get_decay_mode(),
+                    self.config.get_decay_amount(),
+                    self.config.get_decay_factor(),
+                );
+            }
+            self.last_decay += interval * fades;
+        } else {
+            self.last_decay = std::time::Instant::now();
+        }
+    }
+
+    /// Move the main walker to a new position and count the visit
+    fn set_pos(&mut self, x: u32, y: u32) {
+        self.walkers[0] = (x, y);
+        self.previous_pos = None;
+        self.grid.visit(x, y);
+        self.grow_snake();
+    }
+
+    /// Move the snake's head to the main walker, its tail is cut off once the
+    /// body is `snake_length` cells long
+    fn grow_snake(&mut self) {
+        let Some(length) = self.config.get_snake_length() else {
+            self.snake.clear();
+            return;
+        };
+        self.snake.push_front(self.walkers[0]);
+        self.snake.truncate(length as usize);
+    }
+
+    /// Progress of the startup fade-in from 0.0 to 1.0, `None` once it is done
+    fn fade_progress(&self) -> Option<f32> {
+        let start = self.fade_start?;
+        let duration = self.config.get_fade_in_secs();
+        if duration <= 0.0 {
+            return None;
+        }
+        let progress = start.elapsed().as_secs_f32() / duration;
+        (progress < 1.0).then_some(progress)
+    }
+
+    /// Where the active dot of the main walker is with `smooth_steps`, in
+    /// grid cells. It slides from the previous cell to the current one until
+    /// the next step, `None` if it sits on its cell.
+    fn glide_pos(&self) -> Option<(f32, f32)> {
+        // The snake and the compass colors draw the active dot themselves
+        if !self.config.smooth_steps()
+            || !self.config.display_active_field()
+            || self.config.get_snake_length().is_some()
+            || self.config.compass_colors()
+        {
+            return None;
+        }
+        let (from, to) = (self.previous_pos?, self.walkers[0]);
+        // Steps that wrap around the edge or jump aren't slid across the grid
+        if from == to || from.0.abs_diff(to.0) > 1 || from.1.abs_diff(to.1) > 1 {
+            return None;
+        }
+        let progress =
+            self.last_step.elapsed().as_secs_f32() / self.config.walk_interval().as_secs_f32();
+        if progress >= 1.0 {
+            return None;
+        }
+        let slide = |from: u32, to: u32| from as f32 + (to as f32 - from as f32) * progress;
+        Some((slide(from.0, to.0), slide(from.1, to.1)))
+    }
+
+    /// Whether the startup fade-in, ripples or a sliding active dot still
+    /// need animation frames
+    fn is_animating(&self) -> bool {
+        self.fade_start.is_some() || !self.ripples.is_empty() || self.glide_pos().is_some()
+    }
+
+    /// The compositor is ready for a new frame: draw the one that was held
+    /// back or the next animation frame
+    pub fn frame_done(&mut self, shm: &Shm, qh: &QueueHandle<App>) {
+        self.awaiting_frame = false;
+        if std::mem::take(&mut self.needs_draw) || self.is_animating() {
+            self.draw(shm, qh);
+        }
+    }
+
+    /// Draw a new frame. Only the cells that changed since the last frame are
+    /// redrawn if possible.
+    ///
+    /// Frames are drawn when the compositor asks for them, until then the
+    /// draw is held back. While a frame is still rendered on the worker thread
+    /// nothing is drawn, the frame is redrawn once that one has been presented
+    /// instead.
+    pub fn draw(&mut self, shm: &Shm, qh: &QueueHandle<App>) {
+        if let Some(pending) = &mut self.pending_frame {
+            pending.redraw = true;
+            return;
+        }
+        if self.awaiting_frame {
+            self.needs_draw = true;
+            return;
+        }
+        let Some(rotation) = self.prepare_frame() else {
+            return;
+        };
+        let Some(index) = self.acquire_buffer(shm, qh, rotation) else {
+            return;
+        };
+
+        let fade_alpha = self.fade_alpha();
+        let glide = self.glide_pos();
+        let damage = self.dirty_regions(
+            self.buffers.contents(index),
+            rotation,
+            fade_alpha.is_some(),
+            glide.is_some(),
+        );
+        if damage.as_ref().is_some_and(Vec::is_empty) {
+            return;
+        }
+        let input = FrameInput {
+            config: Cow::Borrowed(&self.config),
+            grid: Cow::Borrowed(&self.grid),
+            walkers: Cow::Borrowed(&self.walkers),
+            ghosts: Cow::Borrowed(&self.ghosts),
+            snake: Cow::Borrowed(self.snake.make_contiguous()),
+            previous_pos: self.previous_pos,
+            ripples: Cow::Borrowed(&self.ripples),
+            fade_alpha,
+            glide,
+            width: self.width,
+            height: self.height,
+            rotation,
+        };
+        let buffer = self.buffers.data(index);
+        match &damage {
+            Some(regions) => input.render_regions(regions, buffer),
+            None => input.render(&mut self.scratch, buffer),
+        }
+        let snapshot = input.snapshot();
+        self.buffers.set_contents(index, snapshot.clone());
+        self.presented = snapshot;
+
+        self.present(qh, index, rotation, damage.as_deref());
+    }
+
+    /// A buffer of the current size and format that the compositor isn't
+    /// reading from
+    fn acquire_buffer(
+        &mut self,
+        shm: &Shm,
+        qh: &QueueHandle<App>,
+        rotation: Rotation,
+    ) -> Option<usize> {
+        let (width, height) = rotation.buffer_size(self.width, self.height);
+        let format = shm_format(self.config.get_pixel_format());
+        self.buffers.set_layout(width, height, format);
+        self.buffers.acquire(shm, qh)
+    }
+
+    /// Regions that changed since the frame in the buffer and the last
+    /// presented frame were drawn, `None` if the whole frame has to be drawn
+    /// again
+    fn dirty_regions(
+        &self,
+        contents: Option<&DrawnFrame>,
+        rotation: Rotation,
+        fading: bool,
+        gliding: bool,
+    ) -> Option<Vec<(u32, u32, u32, u32)>> {
+        // Effects that aren't tied to single cells change the whole frame
+        if rotation != Rotation::Normal
+            || fading
+            || !self.ripples.is_empty()
+            || self.config.get_snake_length().is_some()
+            || self.config.compass_colors()
+            || self.config.get_glow_radius() > 0
+            || self.config.get_supersample() > 1
+        {
+            return None;
+        }
+
+        // The buffer is drawn on, the compositor needs to know what changed
+        // since the frame it shows
+        let mut cells = self.changed_cells(contents?)?;
+        cells.extend(self.changed_cells(self.presented.as_ref()?)?);
+        if gliding {
+            cells.extend(self.previous_pos);
+            cells.push(self.walkers[0]);
+        }
+        // Drawing most of the frame piece by piece is slower than at once
+        if cells.len() > self.grid.get_visits_slice().len() / 4 {
+            return None;
+        }
+        cells.sort_unstable();
+        cells.dedup();
+        Some(walk_bg::draw::dirty_regions(
+            &self.config,
+            self.width,
+            self.height,
+            &cells,
+        ))
+    }
+
+    /// Cells that look different than in the `drawn` frame, `None` if the
+    /// grid changed size since
+    fn changed_cells(&self, drawn: &DrawnFrame) -> Option<Vec<(u32, u32)>> {
+        let visits = self.grid.get_visits_slice();
+        if visits.len() != drawn.visits.len() {
+            return None;
+        }
+        let grid_width = self.grid.get_width();
+        let mut cells: Vec<_> = visits
+            .iter()
+            .zip(&drawn.visits)
+            .enumerate()
+            .filter(|(_, (now, before))| now != before)
+            .map(|(index, _)| (index as u32 % grid_width, index as u32 / grid_width))
+            .collect();
+
+        // Ghosts fade by age, so all of them change once the walker moved
+        if drawn.walkers != self.walkers || drawn.ghosts != self.ghosts {
+            cells.extend(drawn.walkers.iter().chain(&drawn.ghosts));
+            cells.extend(self.walkers.iter().chain(&self.ghosts));
+        }
+        // The sliding active dot moved since
+        cells.extend(&drawn.glide_cells);
+        Some(cells)
+    }
+
+    /// Render a full frame on a worker thread, so a slow repaint after a
+    /// resize or reload doesn't hold up the Wayland dispatch.
+    ///
+    /// The frame is presented by [`OutputSurface::present_pending`] once it is
+    /// done. A frame that is still rendering is dropped in favor of the new one.
+    pub fn draw_offloaded(&mut self) {
+        let Some(rotation) = self.prepare_frame() else {
+            return;
+        };
+
+        let input = FrameInput {
+            config: Cow::Owned(self.config.clone()),
+            grid: Cow::Owned(self.grid.clone()),
+            walkers: Cow::Owned(self.walkers.clone()),
+            ghosts: Cow::Owned(self.ghosts.clone()),
+            snake: Cow::Owned(self.snake.iter().copied().collect()),
+            previous_pos: self.previous_pos,
+            ripples: Cow::Owned(self.ripples.clone()),
+            fade_alpha: self.fade_alpha(),
+            glide: self.glide_pos(),
+            width: self.width,
+            height: self.height,
+            rotation,
+        };
+        let size = (self.width * 4 * self.height) as usize;
+        let drawn = input.snapshot();
+        // The config or size changed, so the buffers are outdated
+        self.buffers.clear_contents();
+        self.presented = None;
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let mut buffer = vec![0u8; size];
+            input.render(&mut Scratch::default(), &mut buffer);
+            // Nobody is waiting anymore if a newer frame replaced this one
+            let _ = sender.send(buffer);
+        });
+        self.pending_frame = Some(PendingFrame {
+            receiver,
+            width: self.width,
+            height: self.height,
+            rotation,
+            drawn,
+            redraw: false,
+        });
+    }
+
+    /// Present the frame rendered on the worker thread if it is done. Returns
+    /// `true` if requests were queued.
+    pub fn present_pending(&mut self, shm: &Shm, qh: &QueueHandle<App>) -> bool {
+        let Some(pending) = &self.pending_frame else {
+            return false;
+        };
+
+        // A configure may have changed the size while the frame was rendered
+        let current_rotation = rotation(self.transform).unwrap_or(Rotation::Normal);
+        if (pending.width, pending.height, pending.rotation)
+            != (self.width, self.height, current_rotation)
+        {
+            self.pending_frame = None;
+            self.draw(shm, qh);
+            return true;
+        }
+        // Try again on the next call once a buffer is released
+        let Some(index) = self.acquire_buffer(shm, qh, current_rotation) else {
+            return false;
+        };
+
+        let Some(pending) = &self.pending_frame else {
+            return false;
+        };
+        let frame = match pending.receiver.try_recv() {
+            Ok(frame) => frame,
+            Err(std::sync::mpsc::TryRecvError::Empty) => return false,
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                log::error!("Render thread exited without a frame");
+                self.pending_frame = None;
+                self.draw(shm, qh);
+                return true;
+            }
+        };
+        let pending = self.pending_frame.take().unwrap();
+
+        self.buffers.data(index).copy_from_slice(&frame);
+        self.buffers.set_contents(index, pending.drawn.clone());
+        self.presented = pending.drawn;
+        self.present(qh, index, pending.rotation, None);
+
+        if pending.redraw {
+            self.draw(shm, qh);
+        }
+        true
+    }
+
+    /// Check that the surface can be drawn on, prune finished ripples and
+    /// return the rotation of the buffer
+    fn prepare_frame(&mut self) -> Option<Rotation> {
+        if !self.configured || self.width == 0 || self.height == 0 {
+            return None;
+        }
+        self.ripples
+            .retain(|(_, started)| started.elapsed() < RIPPLE_DURATION);
+        Some(rotation(self.transform).unwrap_or(Rotation::Normal))
+    }
+
+    /// Opacity of the startup fade-in for the next frame, `None` once it is
+    /// done
+    fn fade_alpha(&mut self) -> Option<f32> {
+        let Some(progress) = self.fade_progress() else {
+            self.fade_start = None;
+            return None;
+        };
+        // Smoothstep easing
+        Some(progress * progress * (3.0 - 2.0 * progress))
+    }
+
+    /// Attach the buffer at `index` to the surface and commit it. Only the
+    /// `damage` regions are marked as changed, if given.
+    fn present(
+        &mut self,
+        qh: &QueueHandle<App>,
+        index: usize,
+        rotation: Rotation,
+        damage: Option<&[(u32, u32, u32, u32)]>,
+    ) {
+        let (width, height) = rotation.buffer_size(self.width, self.height);
+
+        let wl_surface = self.layer_surface.wl_surface();
+        // Ask to be told when the compositor wants the next frame
+        wl_surface.frame(qh, wl_surface.clone());
+        self.awaiting_frame = true;
+        self.buffers.attach(index, wl_surface);
+        match damage {
+            Some(regions) => {
+                for &(x, y, region_width, region_height) in regions {
+                    wl_surface.damage_buffer(
+                        x as i32,
+                        y as i32,
+                        region_width as i32,
+                        region_height as i32,
+                    );
+                }
+            }
+            None => wl_surface.damage_buffer(0, 0, width as i32, height as i32),
+        }
+        wl_surface.commit();
+
+        // Only the last second is needed for the frame rate
+        let now = std::time::Instant::now();
+        while self
+            .frame_times
+            .front()
+            .is_some_and(|&time| now - time > std::time::Duration::from_secs(1))
+        {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(now);
+    }
+}
@@ -1,25 +1,77 @@
-use std::os::fd::AsFd;
-
-use smithay_client_toolkit::{
-    compositor::{CompositorHandler, CompositorState},
-    delegate_compositor, delegate_layer, delegate_output, delegate_registry, delegate_shm,
-    output::{OutputHandler, OutputState},
-    registry::{ProvidesRegistryState, RegistryState},
-    registry_handlers,
-    shell::WaylandSurface,
-    shell::wlr_layer::{self, LayerShellHandler, LayerSurface, LayerSurfaceConfigure},
-    shm::{Shm, ShmHandler},
-};
-use wayland_client::{
-    Connection, QueueHandle, globals,
-    protocol::{wl_buffer, wl_output, wl_shm, wl_shm_pool, wl_surface},
-};
+use crate::draw::{BlendMode, ConnectionStyle, DotShape, PixelFormat, RadiusOverlap};
+use crate::utils::{self, BoundaryMode, StuckBehavior, WalkAlgorithm};
+
+/// Upper limit for `warmup_steps`, so a huge value can't stall the startup
+pub const MAX_WARMUP_STEPS: u32 = 1_000_000;
+
+/// Upper limit for options given in seconds, about 30 years. Longer ones
+/// don't fit into a `Duration`.
+pub const MAX_SECS: f32 = 1e9;
+
+/// Upper limit for the steps per minute, one step per millisecond
+pub const MAX_STEPS_PER_MINUTE: f32 = 60_000.0;
+
+/// Upper limit for `preview_width` and `preview_height`
+pub const MAX_PREVIEW_SIZE: u32 = 16384;
+
+/// Built-in color schemes for `theme`: name, background, foreground, active
+/// and heat color
+const THEMES: &[(&str, [u32; 4])] = &[
+    ("gruvbox", [0xff282828, 0xff928374, 0xfffb4934, 0xfffabd2f]),
+    ("nord", [0xff2e3440, 0xff4c566a, 0xff88c0d0, 0xffebcb8b]),
+    ("dracula", [0xff282a36, 0xff6272a4, 0xffff79c6, 0xfff1fa8c]),
+    (
+        "solarized",
+        [0xff002b36, 0xff586e75, 0xffdc322f, 0xffb58900],
+    ),
+];
+
+/// Other names some fields can be written as in the config, as (alias, field)
+pub const FIELD_ALIASES: &[(&str, &str)] = &[
+    ("max_runtime_secs", "run_duration_secs"),
+    ("hot_reload", "watch_config"),
+];
+
+/// The default `anchor`, stretching the surface over the whole output
+fn all_edges() -> Vec<String> {
+    Edge::NAMES.iter().map(|name| name.to_string()).collect()
+}
+
+/// The default `surface_size`, stretching the surface between its edges
+fn no_size() -> (u32, u32) {
+    (0, 0)
+}
+
+/// The default `drift`, facet has no default for tuples
+fn no_drift() -> (f32, f32) {
+    (0.0, 0.0)
+}
+
+/// Take the value of every field named in `keys` from `other`, as when
+/// `other` is a config file that overrides `config`. Flattened groups merge
+/// their own fields and skipped fields keep their value. Every field has to
+/// be listed, so a new one can't be forgotten.
+macro_rules! merge_fields {
+    (
+        $config:ident, $other:ident, $keys:ident, $ty:ident { $($field:ident),* $(,)? }
+        $(flatten { $($group:ident),* $(,)? })?
+        $(skip { $($skipped:ident),* $(,)? })?
+    ) => {{
+        let $ty { $($field,)* $($($group,)*)? $($($skipped: _,)*)? } = $other;
+        $(
+            if $keys.iter().any(|key| key == stringify!($field)) {
+                $config.$field = $field;
+            }
+        )*
+        $($($config.$group.merge($group, $keys);)*)?
+    }};
+}
 
 /// The config file format
 #[derive(facet::Facet, Debug, Clone)]
 pub struct Config {
-    /// How many walks should be performed per minute
-    #[facet(default = 30.0)]
+    /// How many walks should be performed per minute, at most 60000
+    #[facet(default = 30.0f32)]
     walks_per_minute: f32,
     /// How many pixels one grid point should cover
     #[facet(default = 20)]
@@ -27,20 +79,186 @@ pub struct Config {
     /// Size of each individual dot in pixels
     #[facet(default = 2)]
     dot_radius: u32,
-    /// Background color in ARGB format
-    #[facet(default = 0xff1a1a1au32)]
-    bg_color: u32,
-    /// Foreground color in ARGB format
-    #[facet(default = 0xff606060u32)]
-    fg_color: u32,
+    /// Background color
+    #[facet(default = Color::from_argb(0xff1a1a1a))]
+    bg_color: Color,
+    /// Foreground color
+    #[facet(default = Color::from_argb(0xff606060))]
+    fg_color: Color,
     #[facet(default = true)]
     display_active_field: bool,
-    /// The currently active field
-    #[facet(default = 0xffff0000u32)]
-    active_color: u32,
+    /// Color of the currently active field
+    #[facet(default = Color::from_argb(0xffff0000))]
+    active_color: Color,
     /// Whether to connect the dots
     #[facet(default = true)]
     connect_dots: bool,
+    /// Color of the connections, defaults to half the foreground
+    #[facet(default)]
+    connection_color: Option<Color>,
+    /// Opacity of the connections on top of the alpha of their color, 255
+    /// leaves them as they are
+    #[facet(default = 255)]
+    connection_alpha: u8,
+    /// Confine the walk to (x, y, width, height) in grid cells
+    #[facet(default)]
+    walk_bounds: Option<(u32, u32, u32, u32)>,
+    /// Roughly how many dots the grid should have, overrides `pixels_per_point`
+    #[facet(default)]
+    target_dots: Option<u32>,
+    /// Duration of the fade-in on startup in seconds, 0 disables it
+    #[facet(default = 0.0f32)]
+    fade_in_secs: f32,
+    /// Algorithm used to pick the next step
+    #[facet(default = "uniform")]
+    walk_algorithm: String,
+    /// Shape of the active field, one of "circle", "ring", "square",
+    /// "diamond" or "cross"
+    #[facet(default = "circle")]
+    active_shape: String,
+    /// Shape of every other dot, with the same choices as `active_shape`
+    #[facet(default = "circle")]
+    dot_shape: String,
+    /// Radius of the active field in pixels, defaults to the dot radius
+    #[facet(default)]
+    active_radius: Option<u32>,
+    /// Pattern to seed the grid with, one of "none", "checker", "border",
+    /// "diagonal" or "spiral". A path to a PNG image seeds every cell with the
+    /// luminance of the image at that spot, relative paths are resolved from
+    /// the directory of the config file.
+    #[facet(default = "none")]
+    seed_pattern: String,
+    /// Radius of the glow around dots in pixels, 0 disables it.
+    /// Expensive, since it blurs the whole frame on every redraw.
+    #[facet(default = 0)]
+    glow_radius: u32,
+    /// Alpha of dots and connections, the background keeps its own alpha
+    #[facet(default = 255)]
+    dot_alpha: u8,
+    /// What happens at the edge of the grid, one of "clamp", "wrap" or
+    /// "reflect"
+    #[facet(default = "clamp")]
+    boundary: String,
+    /// How connected dots are linked, one of "grid" or "web"
+    #[facet(default = "grid")]
+    connection_style: String,
+    /// Where the walk starts, one of "center" or "cursor". The cursor is only
+    /// known with `interactive`, otherwise the walk starts at the center.
+    #[facet(default = "center")]
+    start: String,
+    /// Brighten dots the more often they were visited
+    #[facet(default = true)]
+    heatmap: bool,
+    /// Visits after which a dot is fully brightened by the heatmap, 0 makes
+    /// every visited dot fully bright
+    #[facet(default = 10)]
+    max_intensity_visits: u8,
+    /// Color the heatmap fades a dot towards, reached at `max_intensity_visits`
+    #[facet(default = Color::from_argb(0xffffc864))]
+    heat_color: Color,
+    /// How dots larger than the spacing are drawn, one of "clip", "blend" or
+    /// "allow"
+    #[facet(default = "clip")]
+    radius_overlap: String,
+    /// Steps walked before the first frame, at most [`MAX_WARMUP_STEPS`]
+    #[facet(default = 0)]
+    warmup_steps: u32,
+    /// Let the background receive pointer input, otherwise clicks pass
+    /// through it
+    #[facet(default = false)]
+    interactive: bool,
+    /// Color of dots that were never visited, `fg_color` if unset
+    #[facet(default)]
+    unvisited_color: Option<Color>,
+    /// How dots are drawn onto what is below them, one of "over", "replace"
+    /// or "add". "over" blends dots and connections using their alpha.
+    #[facet(default = "over")]
+    blend_mode: String,
+    /// Step to this tempo in beats per minute instead of `walks_per_minute`
+    #[facet(default)]
+    bpm: Option<f32>,
+    /// Steps per beat when `bpm` is set
+    #[facet(default = 1)]
+    steps_per_beat: u32,
+    /// Horizontal distance between dots, `pixels_per_point` if unset
+    #[facet(default)]
+    pixels_per_point_x: Option<u32>,
+    /// Vertical distance between dots, `pixels_per_point` if unset
+    #[facet(default)]
+    pixels_per_point_y: Option<u32>,
+    /// How strongly dots fade into the background toward the screen edges,
+    /// 0 disables it
+    #[facet(default = 0.0f32)]
+    vignette: f32,
+    /// Other config files loaded first, this file overrides them. Relative
+    /// paths are resolved from the directory of this file
+    #[facet(default)]
+    include: Vec<String>,
+    /// What happens to the trail when the grid changes size, one of "clear",
+    /// "preserve" or "rescale". Preserving keeps the trail through output mode
+    /// changes
+    #[facet(default = "preserve")]
+    resize_policy: String,
+    /// How much a single visit adds to a cell, counts saturate at 255
+    #[facet(default = 1)]
+    visit_increment: u8,
+    /// Width of the frame drawn along the screen edges, 0 disables it
+    #[facet(default = 0)]
+    border_width: u32,
+    /// Color of the frame along the screen edges
+    #[facet(default = Color::from_argb(0xff606060))]
+    border_color: Color,
+    /// Largest number of cells in the grid, the spacing grows until the grid
+    /// fits
+    #[facet(default = 4_000_000)]
+    max_grid_cells: u64,
+    /// Previous positions that keep a fading active highlight, 0 disables it
+    #[facet(default = 0)]
+    ghost_steps: u32,
+    /// Fade each connection between the colors of the dots it connects
+    /// instead of using `connection_color`
+    #[facet(default = false)]
+    gradient_connections: bool,
+    /// Send a fading ring out from every step, like a ripple on water
+    #[facet(default = false)]
+    ripple: bool,
+    /// Only draw the last this many positions of the main walker, like a snake,
+    /// instead of every visited cell
+    #[facet(default)]
+    snake_length: Option<u32>,
+    /// Slide the active dot from one cell to the next over the time between
+    /// two steps instead of jumping. Redraws the frame at the refresh rate.
+    #[facet(default = false)]
+    smooth_steps: bool,
+    /// Seed for the random walk, the same seed repeats the same walk. Seeded
+    /// from system entropy if unset.
+    #[facet(default)]
+    seed: Option<u64>,
+    /// Built-in color scheme, one of "gruvbox", "nord", "dracula" or
+    /// "solarized". Sets the background, foreground, active and heat colors
+    /// unless the config sets them itself
+    #[facet(default)]
+    theme: Option<String>,
+    // facet tracks at most 63 fields per struct, so related options live in
+    // groups. Flattening keeps their keys at the top level of the file.
+    /// Where the background surface is placed and how its buffers are laid out
+    #[facet(flatten)]
+    surface: SurfaceConfig,
+    /// How the walkers move and how many there are
+    #[facet(flatten)]
+    walkers: WalkerConfig,
+    /// How visited cells fade again
+    #[facet(flatten)]
+    decay: DecayConfig,
+    /// Colors of the active dot by the direction it moved in
+    #[facet(flatten)]
+    compass: CompassConfig,
+    /// How the daemon runs, logs and reports
+    #[facet(flatten)]
+    daemon: DaemonConfig,
+    /// The image rendered by `--output-file` and `--dry-run`
+    #[facet(flatten)]
+    preview: PreviewConfig,
 }
 
 /// Needs to be manually implemented because facets default only happens when
@@ -51,19 +269,801 @@ impl Default for Config {
             walks_per_minute: 30.0,
             pixels_per_point: 20,
             dot_radius: 2,
-            bg_color: 0xff1a1a1au32,
-            fg_color: 0xff606060u32,
+            bg_color: Color::from_argb(0xff1a1a1a),
+            fg_color: Color::from_argb(0xff606060),
             display_active_field: true,
-            active_color: 0xffff0000u32,
+            active_color: Color::from_argb(0xffff0000),
             connect_dots: true,
+            connection_color: None,
+            connection_alpha: 255,
+            walk_bounds: None,
+            target_dots: None,
+            fade_in_secs: 0.0,
+            walk_algorithm: "uniform".to_string(),
+            active_shape: "circle".to_string(),
+            dot_shape: "circle".to_string(),
+            active_radius: None,
+            seed_pattern: "none".to_string(),
+            glow_radius: 0,
+            dot_alpha: 255,
+            boundary: "clamp".to_string(),
+            connection_style: "grid".to_string(),
+            start: "center".to_string(),
+            heatmap: true,
+            max_intensity_visits: 10,
+            heat_color: Color::from_argb(0xffffc864),
+            radius_overlap: "clip".to_string(),
+            warmup_steps: 0,
+            interactive: false,
+            unvisited_color: None,
+            blend_mode: "over".to_string(),
+            bpm: None,
+            steps_per_beat: 1,
+            pixels_per_point_x: None,
+            pixels_per_point_y: None,
+            vignette: 0.0,
+            include: Vec::new(),
+            resize_policy: "preserve".to_string(),
+            visit_increment: 1,
+            border_width: 0,
+            border_color: Color::from_argb(0xff606060),
+            max_grid_cells: 4_000_000,
+            ghost_steps: 0,
+            gradient_connections: false,
+            ripple: false,
+            snake_length: None,
+            smooth_steps: false,
+            seed: None,
+            theme: None,
+            surface: SurfaceConfig::default(),
+            walkers: WalkerConfig::default(),
+            decay: DecayConfig::default(),
+            compass: CompassConfig::default(),
+            daemon: DaemonConfig::default(),
+            preview: PreviewConfig::default(),
         }
     }
 }
 
+/// Where the background surface is placed and how its buffers are laid out
+#[derive(facet::Facet, Debug, Clone)]
+struct SurfaceConfig {
+    /// Output to draw on, either its name like "DP-3" or "Make Model" as
+    /// reported by the output. Every output gets its own background if unset
+    #[facet(default)]
+    output: Option<String>,
+    /// Layer the walk is drawn on, one of "background", "bottom", "top" or
+    /// "overlay". "bottom" sits above the wallpaper but below windows
+    #[facet(default = "background")]
+    layer: String,
+    /// Screen edges the surface sticks to, any of "top", "bottom", "left" and
+    /// "right"
+    #[facet(default = all_edges())]
+    anchor: Vec<String>,
+    /// Width and height of the surface, 0 stretches it between the anchored
+    /// edges on both sides
+    #[facet(default = no_size())]
+    surface_size: (u32, u32),
+    /// Space the surface keeps windows away from along the anchored edge, -1
+    /// lets it cover everything
+    #[facet(default = -1)]
+    exclusive_zone: i32,
+    /// Largest width or height of the surface in pixels, bigger configure
+    /// sizes are capped to it
+    #[facet(default = 16384)]
+    max_surface_size: u32,
+    /// Pixel format of the buffers, "argb8888" or "xrgb8888". Without alpha
+    /// every dot is drawn opaque
+    #[facet(default = "argb8888")]
+    pixel_format: String,
+    /// Render frames this many times larger and shrink them down, which
+    /// smooths the edges of dots and connections. 1 to 4, every step costs a
+    /// lot more drawing time
+    #[facet(default = 1)]
+    supersample: u32,
+}
+
+impl Default for SurfaceConfig {
+    fn default() -> Self {
+        SurfaceConfig {
+            output: None,
+            layer: "background".to_string(),
+            anchor: all_edges(),
+            surface_size: no_size(),
+            exclusive_zone: -1,
+            max_surface_size: 16384,
+            pixel_format: "argb8888".to_string(),
+            supersample: 1,
+        }
+    }
+}
+
+impl SurfaceConfig {
+    fn merge(&mut self, other: Self, keys: &[String]) {
+        merge_fields!(
+            self,
+            other,
+            keys,
+            SurfaceConfig {
+                output,
+                layer,
+                anchor,
+                surface_size,
+                exclusive_zone,
+                max_surface_size,
+                pixel_format,
+                supersample,
+            }
+        );
+    }
+}
+
+/// How the walkers move and how many there are
+#[derive(facet::Facet, Debug, Clone)]
+struct WalkerConfig {
+    /// Number of walkers the walk starts with, next to each other around the
+    /// start. Raises `max_walkers` if that is lower.
+    #[facet(default = 1)]
+    walker_count: u32,
+    /// Chance per step that a new walker appears on a random cell
+    #[facet(default = 0.0f32)]
+    spawn_prob: f32,
+    /// Chance per step that the newest walker is retired
+    #[facet(default = 0.0f32)]
+    retire_prob: f32,
+    /// Fewest walkers on the grid, at least 1
+    #[facet(default = 1)]
+    min_walkers: u32,
+    /// Most walkers on the grid
+    #[facet(default = 1)]
+    max_walkers: u32,
+    /// Bias of the uniform walk as (x, y), positive values favor moving right
+    /// and down. Each direction is weighted with `1 + drift · direction`
+    #[facet(default = no_drift())]
+    drift: (f32, f32),
+    /// Chance from 0 to 1 that a walker keeps moving in the direction of its
+    /// last random move, for longer straight lines
+    #[facet(default = 0.0f32)]
+    momentum: f32,
+    /// Let the uniform walk make long jumps with power-law distributed lengths,
+    /// smaller values jump further. Every step is a single cell if unset
+    #[facet(default)]
+    levy_alpha: Option<f32>,
+    /// Directions the walker may move in, any of "up", "down", "left",
+    /// "right", "up_left", "up_right", "down_left" and "down_right".
+    /// Empty means up, down, left and right
+    #[facet(default)]
+    allowed_directions: Vec<String>,
+    /// Also move to the four diagonal neighbors, and connect diagonally
+    /// adjacent dots
+    #[facet(default = false)]
+    diagonals: bool,
+    /// What a walker without a preferred move does, one of "random",
+    /// "teleport" or "reset"
+    #[facet(default = "random")]
+    stuck_behavior: String,
+    /// Also visit the cells a Lévy flight or a teleport passes over, so the
+    /// trail stays connected, instead of only where it lands
+    #[facet(default = false)]
+    jump_visits_path: bool,
+}
+
+impl Default for WalkerConfig {
+    fn default() -> Self {
+        WalkerConfig {
+            walker_count: 1,
+            spawn_prob: 0.0,
+            retire_prob: 0.0,
+            min_walkers: 1,
+            max_walkers: 1,
+            drift: no_drift(),
+            momentum: 0.0,
+            levy_alpha: None,
+            allowed_directions: Vec::new(),
+            diagonals: false,
+            stuck_behavior: "random".to_string(),
+            jump_visits_path: false,
+        }
+    }
+}
+
+impl WalkerConfig {
+    fn merge(&mut self, other: Self, keys: &[String]) {
+        merge_fields!(
+            self,
+            other,
+            keys,
+            WalkerConfig {
+                walker_count,
+                spawn_prob,
+                retire_prob,
+                min_walkers,
+                max_walkers,
+                drift,
+                momentum,
+                levy_alpha,
+                allowed_directions,
+                diagonals,
+                stuck_behavior,
+                jump_visits_path,
+            }
+        );
+    }
+}
+
+/// How visited cells fade again
+#[derive(facet::Facet, Debug, Clone)]
+struct DecayConfig {
+    /// Seconds between two fades of every cell's visit count, 0 disables it
+    #[facet(default = 0.0f32)]
+    decay_interval_secs: f32,
+    /// How cells fade, "linear" subtracts `decay_amount`, "exponential"
+    /// multiplies by `decay_factor`
+    #[facet(default = "linear")]
+    decay_mode: String,
+    /// Visits taken from every cell per fade in linear mode
+    #[facet(default = 1)]
+    decay_amount: u8,
+    /// Part of the visits every cell keeps per fade in exponential mode
+    #[facet(default = 0.9f32)]
+    decay_factor: f32,
+    /// Visits every cell loses per minute, one at a time. A shorthand for
+    /// linear decay that can't be combined with `decay_interval_secs`
+    #[facet(default)]
+    decay_per_minute: Option<u8>,
+    /// Forget cells that weren't visited for this many seconds, 0 disables it
+    #[facet(default = 0.0f32)]
+    trail_max_age_secs: f32,
+}
+
+impl Default for DecayConfig {
+    fn default() -> Self {
+        DecayConfig {
+            decay_interval_secs: 0.0,
+            decay_mode: "linear".to_string(),
+            decay_amount: 1,
+            decay_factor: 0.9,
+            decay_per_minute: None,
+            trail_max_age_secs: 0.0,
+        }
+    }
+}
+
+impl DecayConfig {
+    fn merge(&mut self, other: Self, keys: &[String]) {
+        merge_fields!(
+            self,
+            other,
+            keys,
+            DecayConfig {
+                decay_interval_secs,
+                decay_mode,
+                decay_amount,
+                decay_factor,
+                decay_per_minute,
+                trail_max_age_secs,
+            }
+        );
+    }
+}
+
+/// Colors of the active dot by the direction it moved in
+#[derive(facet::Facet, Debug, Clone)]
+struct CompassConfig {
+    /// Draw the newest step in the color of the direction it went
+    #[facet(default = false)]
+    compass_colors: bool,
+    /// Color of steps going up, diagonal steps use the up or down color
+    #[facet(default = Color::from_argb(0xff3080ff))]
+    compass_up_color: Color,
+    /// Color of steps going right
+    #[facet(default = Color::from_argb(0xff30c050))]
+    compass_right_color: Color,
+    /// Color of steps going down, diagonal steps use the up or down color
+    #[facet(default = Color::from_argb(0xffe03030))]
+    compass_down_color: Color,
+    /// Color of steps going left
+    #[facet(default = Color::from_argb(0xffe0c030))]
+    compass_left_color: Color,
+}
+
+impl Default for CompassConfig {
+    fn default() -> Self {
+        CompassConfig {
+            compass_colors: false,
+            compass_up_color: Color::from_argb(0xff3080ff),
+            compass_right_color: Color::from_argb(0xff30c050),
+            compass_down_color: Color::from_argb(0xffe03030),
+            compass_left_color: Color::from_argb(0xffe0c030),
+        }
+    }
+}
+
+impl CompassConfig {
+    fn merge(&mut self, other: Self, keys: &[String]) {
+        merge_fields!(
+            self,
+            other,
+            keys,
+            CompassConfig {
+                compass_colors,
+                compass_up_color,
+                compass_right_color,
+                compass_down_color,
+                compass_left_color,
+            }
+        );
+    }
+}
+
+/// How the daemon runs, logs and reports
+#[derive(facet::Facet, Debug, Clone)]
+struct DaemonConfig {
+    /// Default log level, overridden by `RUST_LOG` and `--verbose`
+    #[facet(default = "info")]
+    log_level: String,
+    /// Reload the config automatically when the file changes. Can also be
+    /// written as `hot_reload`
+    #[facet(default = false, alias = "hot_reload")]
+    watch_config: bool,
+    /// Save the walk on exit and continue it on the next start
+    #[facet(default = false)]
+    persist_state: bool,
+    /// Answer commands like `stats` on a Unix socket in the runtime directory
+    #[facet(default = false)]
+    control_socket: bool,
+    /// File the walk statistics are written to every second, for status bars
+    #[facet(default)]
+    status_file: Option<String>,
+    /// Exit after running for this many seconds, runs forever if unset. Can
+    /// also be written as `max_runtime_secs`
+    #[facet(default, alias = "max_runtime_secs")]
+    run_duration_secs: Option<f32>,
+    /// Exit after this many walk steps, runs forever if unset
+    #[facet(default)]
+    max_steps: Option<u64>,
+    /// Save the last frame as a PNG to this path when `run_duration_secs` or
+    /// `max_steps` is reached
+    #[facet(default)]
+    exit_screenshot: Option<String>,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        DaemonConfig {
+            log_level: "info".to_string(),
+            watch_config: false,
+            persist_state: false,
+            control_socket: false,
+            status_file: None,
+            run_duration_secs: None,
+            max_steps: None,
+            exit_screenshot: None,
+        }
+    }
+}
+
+impl DaemonConfig {
+    fn merge(&mut self, other: Self, keys: &[String]) {
+        merge_fields!(
+            self,
+            other,
+            keys,
+            DaemonConfig {
+                log_level,
+                watch_config,
+                persist_state,
+                control_socket,
+                status_file,
+                run_duration_secs,
+                max_steps,
+                exit_screenshot,
+            }
+        );
+    }
+}
+
+/// The image rendered by `--output-file` and `--dry-run`
+#[derive(facet::Facet, Debug, Clone)]
+struct PreviewConfig {
+    /// Width of the image written by `--output-file`, at most [`MAX_PREVIEW_SIZE`]
+    #[facet(default = 1920)]
+    preview_width: u32,
+    /// Height of the image written by `--output-file`, at most [`MAX_PREVIEW_SIZE`]
+    #[facet(default = 1080)]
+    preview_height: u32,
+    /// Number of walk steps before the image for `--output-file` is written
+    #[facet(default = 1000)]
+    preview_steps: u32,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        PreviewConfig {
+            preview_width: 1920,
+            preview_height: 1080,
+            preview_steps: 1000,
+        }
+    }
+}
+
+impl PreviewConfig {
+    fn merge(&mut self, other: Self, keys: &[String]) {
+        merge_fields!(
+            self,
+            other,
+            keys,
+            PreviewConfig {
+                preview_width,
+                preview_height,
+                preview_steps,
+            }
+        );
+    }
+}
+
 impl Config {
+    /// Check the config for values the walk or the renderer can't work with
+    pub fn validate(&self) -> Result<(), String> {
+        if self.pixels_per_point == 0 {
+            return Err("pixels_per_point must be at least 1".to_string());
+        }
+        if self.pixels_per_point_x == Some(0) || self.pixels_per_point_y == Some(0) {
+            return Err("pixels_per_point_x and pixels_per_point_y must be at least 1".to_string());
+        }
+        if self.walks_per_minute.is_nan() || self.walks_per_minute <= 0.0 {
+            return Err("walks_per_minute must be greater than 0".to_string());
+        }
+        if let Some(bpm) = self.bpm
+            && (bpm.is_nan() || bpm <= 0.0)
+        {
+            return Err("bpm must be greater than 0".to_string());
+        }
+        if self.steps_per_beat == 0 {
+            return Err("steps_per_beat must be greater than 0".to_string());
+        }
+        // The time between two steps has to fit into a `Duration`
+        let min_steps_per_minute = 60.0 / MAX_SECS;
+        if !(min_steps_per_minute..=MAX_STEPS_PER_MINUTE).contains(&self.steps_per_minute()) {
+            return Err(format!(
+                "walks_per_minute, or bpm times steps_per_beat, must be between \
+                 {min_steps_per_minute} and {MAX_STEPS_PER_MINUTE}"
+            ));
+        }
+        if self.visit_increment == 0 {
+            return Err("visit_increment must be at least 1".to_string());
+        }
+        if self.daemon.log_level.parse::<log::LevelFilter>().is_err() {
+            return Err(format!("unknown log_level '{}'", self.daemon.log_level));
+        }
+        if let Some((_, _, w, h)) = self.walk_bounds
+            && (w == 0 || h == 0)
+        {
+            return Err("walk_bounds must have a non-zero width and height".to_string());
+        }
+        if !(1..=MAX_PREVIEW_SIZE).contains(&self.preview.preview_width)
+            || !(1..=MAX_PREVIEW_SIZE).contains(&self.preview.preview_height)
+        {
+            return Err(format!(
+                "preview_width and preview_height must be between 1 and {MAX_PREVIEW_SIZE}"
+            ));
+        }
+        if self.target_dots == Some(0) {
+            return Err("target_dots must be at least 1".to_string());
+        }
+        if self.snake_length == Some(0) {
+            return Err("snake_length must be at least 1".to_string());
+        }
+        if self.surface.max_surface_size == 0 {
+            return Err("max_surface_size must be at least 1".to_string());
+        }
+        if self.max_grid_cells == 0 {
+            return Err("max_grid_cells must be at least 1".to_string());
+        }
+        if self.fade_in_secs.is_nan() || self.fade_in_secs < 0.0 {
+            return Err("fade_in_secs must not be negative".to_string());
+        }
+        if let Some(secs) = self.daemon.run_duration_secs
+            && !(secs > 0.0 && secs <= MAX_SECS)
+        {
+            return Err(format!(
+                "run_duration_secs must be positive and at most {MAX_SECS}"
+            ));
+        }
+        if self.daemon.max_steps == Some(0) {
+            return Err("max_steps must be at least 1".to_string());
+        }
+        if self.vignette.is_nan() || self.vignette < 0.0 {
+            return Err("vignette must not be negative".to_string());
+        }
+        if !(0.0..=MAX_SECS).contains(&self.decay.decay_interval_secs) {
+            return Err(format!(
+                "decay_interval_secs must be between 0 and {MAX_SECS}"
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.walkers.spawn_prob)
+            || !(0.0..=1.0).contains(&self.walkers.retire_prob)
+        {
+            return Err("spawn_prob and retire_prob must be between 0 and 1".to_string());
+        }
+        if self
+            .walkers
+            .levy_alpha
+            .is_some_and(|alpha| !alpha.is_finite() || alpha <= 0.0)
+        {
+            return Err("levy_alpha must be positive".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.walkers.momentum) {
+            return Err("momentum must be between 0 and 1".to_string());
+        }
+        if !self.walkers.drift.0.is_finite() || !self.walkers.drift.1.is_finite() {
+            return Err("drift must be finite".to_string());
+        }
+        if self.walkers.walker_count == 0 {
+            return Err("walker_count must be at least 1".to_string());
+        }
+        if self.walkers.min_walkers == 0 {
+            return Err("min_walkers must be at least 1".to_string());
+        }
+        if self.walkers.max_walkers < self.walkers.min_walkers {
+            return Err("max_walkers must be at least min_walkers".to_string());
+        }
+        if self.decay.decay_per_minute == Some(0) {
+            return Err("decay_per_minute must be at least 1".to_string());
+        }
+        if self.decay.decay_per_minute.is_some() && self.decay.decay_interval_secs > 0.0 {
+            return Err("decay_per_minute and decay_interval_secs can't both be set".to_string());
+        }
+        if self.decay.decay_amount == 0 {
+            return Err("decay_amount must be at least 1".to_string());
+        }
+        if !(self.decay.decay_factor > 0.0 && self.decay.decay_factor < 1.0) {
+            return Err("decay_factor must be between 0 and 1".to_string());
+        }
+        if !(0.0..=MAX_SECS).contains(&self.decay.trail_max_age_secs) {
+            return Err(format!(
+                "trail_max_age_secs must be between 0 and {MAX_SECS}"
+            ));
+        }
+        if WalkAlgorithm::from_name(&self.walk_algorithm).is_none() {
+            return Err(format!(
+                "unknown walk_algorithm '{}', expected one of {:?}",
+                self.walk_algorithm,
+                WalkAlgorithm::NAMES
+            ));
+        }
+        if let Some(direction) = self
+            .walkers
+            .allowed_directions
+            .iter()
+            .find(|direction| utils::direction_from_name(direction).is_none())
+        {
+            let names: Vec<_> = utils::DIRECTION_NAMES
+                .iter()
+                .map(|(name, _)| name)
+                .collect();
+            return Err(format!(
+                "unknown direction '{}' in allowed_directions, expected one of {:?}",
+                direction, names
+            ));
+        }
+        if let Some((_, _, w, h)) = self.walk_bounds {
+            self.check_directions(w, h)?;
+        }
+        if StuckBehavior::from_name(&self.walkers.stuck_behavior).is_none() {
+            return Err(format!(
+                "unknown stuck_behavior '{}', expected one of {:?}",
+                self.walkers.stuck_behavior,
+                StuckBehavior::NAMES
+            ));
+        }
+        if DotShape::from_name(&self.active_shape).is_none() {
+            return Err(format!(
+                "unknown active_shape '{}', expected one of {:?}",
+                self.active_shape,
+                DotShape::NAMES
+            ));
+        }
+        if DotShape::from_name(&self.dot_shape).is_none() {
+            return Err(format!(
+                "unknown dot_shape '{}', expected one of {:?}",
+                self.dot_shape,
+                DotShape::NAMES
+            ));
+        }
+        if ConnectionStyle::from_name(&self.connection_style).is_none() {
+            return Err(format!(
+                "unknown connection_style '{}', expected one of {:?}",
+                self.connection_style,
+                ConnectionStyle::NAMES
+            ));
+        }
+        if StartPosition::from_name(&self.start).is_none() {
+            return Err(format!(
+                "unknown start '{}', expected one of {:?}",
+                self.start,
+                StartPosition::NAMES
+            ));
+        }
+        if BlendMode::from_name(&self.blend_mode).is_none() {
+            return Err(format!(
+                "unknown blend_mode '{}', expected one of {:?}",
+                self.blend_mode,
+                BlendMode::NAMES
+            ));
+        }
+        if RadiusOverlap::from_name(&self.radius_overlap).is_none() {
+            return Err(format!(
+                "unknown radius_overlap '{}', expected one of {:?}",
+                self.radius_overlap,
+                RadiusOverlap::NAMES
+            ));
+        }
+        if ResizePolicy::from_name(&self.resize_policy).is_none() {
+            return Err(format!(
+                "unknown resize_policy '{}', expected one of {:?}",
+                self.resize_policy,
+                ResizePolicy::NAMES
+            ));
+        }
+        if LayerKind::from_name(&self.surface.layer).is_none() {
+            return Err(format!(
+                "unknown layer '{}', expected one of {:?}",
+                self.surface.layer,
+                LayerKind::NAMES
+            ));
+        }
+        if let Some(name) = self
+            .surface
+            .anchor
+            .iter()
+            .find(|name| Edge::from_name(name).is_none())
+        {
+            return Err(format!(
+                "unknown anchor '{name}', expected any of {:?}",
+                Edge::NAMES
+            ));
+        }
+        // The compositor can only pick a size between two anchored edges
+        let anchor = self.get_anchor();
+        if self.surface.surface_size.0 == 0
+            && !(anchor.contains(&Edge::Left) && anchor.contains(&Edge::Right))
+        {
+            return Err("a surface_size width of 0 needs the left and right anchor".to_string());
+        }
+        if self.surface.surface_size.1 == 0
+            && !(anchor.contains(&Edge::Top) && anchor.contains(&Edge::Bottom))
+        {
+            return Err("a surface_size height of 0 needs the top and bottom anchor".to_string());
+        }
+        if !(1..=4).contains(&self.surface.supersample) {
+            return Err("supersample must be between 1 and 4".to_string());
+        }
+        if PixelFormat::from_name(&self.surface.pixel_format).is_none() {
+            return Err(format!(
+                "unknown pixel_format '{}', expected one of {:?}",
+                self.surface.pixel_format,
+                PixelFormat::NAMES
+            ));
+        }
+        if DecayMode::from_name(&self.decay.decay_mode).is_none() {
+            return Err(format!(
+                "unknown decay_mode '{}', expected one of {:?}",
+                self.decay.decay_mode,
+                DecayMode::NAMES
+            ));
+        }
+        match SeedPattern::from_name(&self.seed_pattern) {
+            None => {
+                return Err(format!(
+                    "unknown seed_pattern '{}', expected one of {:?} or a path to a PNG image",
+                    self.seed_pattern,
+                    SeedPattern::NAMES
+                ));
+            }
+            Some(SeedPattern::Image(path)) if !path.is_file() => {
+                return Err(format!(
+                    "seed_pattern image '{}' doesn't exist",
+                    path.display()
+                ));
+            }
+            Some(_) => {}
+        }
+        if BoundaryMode::from_name(&self.boundary).is_none() {
+            return Err(format!(
+                "unknown boundary '{}', expected one of {:?}",
+                self.boundary,
+                BoundaryMode::NAMES
+            ));
+        }
+        Ok(())
+    }
+
+    /// Steps per minute, from the tempo if `bpm` is set
+    fn steps_per_minute(&self) -> f32 {
+        match self.bpm {
+            Some(bpm) => bpm * self.steps_per_beat as f32,
+            None => self.walks_per_minute,
+        }
+    }
+
+    /// Files to load before this config
+    pub fn get_include(&self) -> &[String] {
+        &self.include
+    }
+
+    /// Take the value of every field named in `keys` from `other`, as when
+    /// `other` is a config file that overrides this one
+    pub fn merge(&mut self, other: Config, keys: &[String]) {
+        merge_fields!(self, other, keys, Config {
+            walks_per_minute,
+            pixels_per_point,
+            dot_radius,
+            bg_color,
+            fg_color,
+            display_active_field,
+            active_color,
+            connect_dots,
+            connection_color,
+            connection_alpha,
+            walk_bounds,
+            target_dots,
+            fade_in_secs,
+            walk_algorithm,
+            active_shape,
+            dot_shape,
+            active_radius,
+            seed_pattern,
+            glow_radius,
+            dot_alpha,
+            boundary,
+            connection_style,
+            start,
+            heatmap,
+            max_intensity_visits,
+            heat_color,
+            radius_overlap,
+            warmup_steps,
+            interactive,
+            unvisited_color,
+            blend_mode,
+            bpm,
+            steps_per_beat,
+            pixels_per_point_x,
+            pixels_per_point_y,
+            vignette,
+            resize_policy,
+            visit_increment,
+            border_width,
+            border_color,
+            max_grid_cells,
+            ghost_steps,
+            gradient_connections,
+            ripple,
+            snake_length,
+            smooth_steps,
+            seed,
+            theme,
+        } flatten {
+            surface,
+            walkers,
+            decay,
+            compass,
+            daemon,
+            preview,
+        } skip {
+            include,
+        });
+    }
+
+    /// Get the time between two walk steps
+    pub fn walk_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs_f32(60.0 / self.steps_per_minute())
+    }
+
     /// Get the walks per second
     pub fn walks_per_second(&self) -> f32 {
-        self.walks_per_minute / 60.0
+        self.steps_per_minute() / 60.0
     }
 
     pub fn get_dot_radius(&self) -> u32 {
@@ -71,435 +1071,1203 @@ impl Config {
     }
 
     pub fn get_bg_color(&self) -> u32 {
-        self.bg_color
+        self.bg_color.argb()
     }
 
     pub fn get_fg_color(&self) -> u32 {
-        self.fg_color
+        self.fg_color.argb()
     }
 
     pub fn get_pixels_per_point(&self) -> u32 {
         self.pixels_per_point
     }
 
+    pub fn set_pixels_per_point(&mut self, pixels_per_point: u32) {
+        self.pixels_per_point = pixels_per_point;
+    }
+
+    /// Multiply every length given in pixels by `scale`, for buffers with
+    /// `scale` pixels per surface pixel
+    pub fn scale_lengths(&mut self, scale: u32) {
+        self.pixels_per_point *= scale;
+        self.pixels_per_point_x = self.pixels_per_point_x.map(|spacing| spacing * scale);
+        self.pixels_per_point_y = self.pixels_per_point_y.map(|spacing| spacing * scale);
+        self.dot_radius *= scale;
+        self.active_radius = self.active_radius.map(|radius| radius * scale);
+        self.glow_radius *= scale;
+        self.border_width *= scale;
+    }
+
+    /// Horizontal and vertical distance between dots in pixels
+    pub fn get_spacing(&self) -> (u32, u32) {
+        (
+            self.pixels_per_point_x.unwrap_or(self.pixels_per_point),
+            self.pixels_per_point_y.unwrap_or(self.pixels_per_point),
+        )
+    }
+
+    /// Pixels per point for a surface of the given size, derived from
+    /// `target_dots` if it is set
+    pub fn spacing_for(&self, width: u32, height: u32) -> u32 {
+        match self.target_dots {
+            Some(target_dots) => {
+                let area = width as f64 * height as f64;
+                ((area / target_dots as f64).sqrt() as u32).max(1)
+            }
+            None => self.pixels_per_point,
+        }
+    }
+
+    /// Grid dimensions for a surface of the given size
+    pub fn grid_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let (spacing_x, spacing_y) = self.get_spacing();
+        ((width / spacing_x) + 1, (height / spacing_y) + 1)
+    }
+
+    /// Widen the spacing until the grid for a surface of the given size has at
+    /// most `max_grid_cells` cells. Returns `true` if the spacing changed.
+    pub fn fit_max_grid_cells(&mut self, width: u32, height: u32) -> bool {
+        let fits = |config: &Config| {
+            let (grid_width, grid_height) = config.grid_size(width, height);
+            grid_width as u64 * grid_height as u64 <= config.max_grid_cells
+        };
+
+        let mut changed = false;
+        // Once the spacing is past the surface size only one cell is left
+        while !fits(self) {
+            self.pixels_per_point += 1;
+            self.pixels_per_point_x = self.pixels_per_point_x.map(|spacing| spacing + 1);
+            self.pixels_per_point_y = self.pixels_per_point_y.map(|spacing| spacing + 1);
+            changed = true;
+        }
+        changed
+    }
+
+    /// The part of a grid the walk is confined to as (x, y, width, height).
+    ///
+    /// Falls back to the full grid if no bounds are configured or they don't fit.
+    pub fn walk_bounds_in(&self, grid_width: u32, grid_height: u32) -> (u32, u32, u32, u32) {
+        match self.walk_bounds {
+            Some((x, y, w, h))
+                if x.saturating_add(w) <= grid_width && y.saturating_add(h) <= grid_height =>
+            {
+                (x, y, w, h)
+            }
+            _ => (0, 0, grid_width, grid_height),
+        }
+    }
+
     pub fn get_active_color(&self) -> u32 {
-        self.active_color
+        self.active_color.argb()
     }
 
     pub fn get_walks_per_minute(&self) -> f32 {
         self.walks_per_minute
     }
 
+    /// Set the speed directly, dropping `bpm` so it does not win over it
+    pub fn set_walks_per_minute(&mut self, walks_per_minute: f32) {
+        self.walks_per_minute = walks_per_minute;
+        self.bpm = None;
+    }
+
     pub fn connect_dots(&self) -> bool {
         self.connect_dots
     }
 
+    pub fn heatmap(&self) -> bool {
+        self.heatmap
+    }
+
+    pub fn get_max_intensity_visits(&self) -> u8 {
+        self.max_intensity_visits
+    }
+
+    pub fn get_heat_color(&self) -> u32 {
+        self.heat_color.argb()
+    }
+
+    pub fn get_output(&self) -> Option<&str> {
+        self.surface.output.as_deref()
+    }
+
+    pub fn get_layer(&self) -> LayerKind {
+        LayerKind::from_name(&self.surface.layer).unwrap_or(LayerKind::Background)
+    }
+
+    /// Edges the surface is anchored to, unknown names are skipped
+    pub fn get_anchor(&self) -> Vec<Edge> {
+        self.surface
+            .anchor
+            .iter()
+            .filter_map(|name| Edge::from_name(name))
+            .collect()
+    }
+
+    pub fn get_surface_size(&self) -> (u32, u32) {
+        self.surface.surface_size
+    }
+
+    pub fn get_exclusive_zone(&self) -> i32 {
+        self.surface.exclusive_zone
+    }
+
+    pub fn interactive(&self) -> bool {
+        self.interactive
+    }
+
+    pub fn control_socket(&self) -> bool {
+        self.daemon.control_socket
+    }
+
     pub fn display_active_field(&self) -> bool {
         self.display_active_field
     }
-}
 
-pub struct WalkState {
-    grid_width: u32,
-    grid_height: u32,
-    current_pos: (u32, u32),
-    needs_update: bool,
-}
+    pub fn get_connection_color(&self) -> Option<u32> {
+        self.connection_color.map(Color::argb)
+    }
+
+    pub fn get_connection_alpha(&self) -> u8 {
+        self.connection_alpha
+    }
+
+    pub fn get_log_level(&self) -> &str {
+        &self.daemon.log_level
+    }
+
+    pub fn get_walk_bounds(&self) -> Option<(u32, u32, u32, u32)> {
+        self.walk_bounds
+    }
+
+    pub fn get_target_dots(&self) -> Option<u32> {
+        self.target_dots
+    }
+
+    pub fn get_fade_in_secs(&self) -> f32 {
+        self.fade_in_secs
+    }
+
+    pub fn get_walk_algorithm(&self) -> &str {
+        &self.walk_algorithm
+    }
 
-impl WalkState {
-    pub fn new(grid_width: u32, grid_height: u32) -> Self {
-        WalkState {
-            grid_width,
-            grid_height,
-            current_pos: (0, 0),
-            needs_update: false,
+    /// Offsets of the directions the walker may move in, never empty
+    pub fn get_allowed_directions(&self) -> Vec<(i64, i64)> {
+        let mut directions: Vec<_> = self
+            .walkers
+            .allowed_directions
+            .iter()
+            .filter_map(|name| utils::direction_from_name(name))
+            .collect();
+        if directions.is_empty() {
+            directions = utils::DIRECTIONS.to_vec();
+        }
+        if self.walkers.diagonals {
+            for diagonal in utils::DIAGONALS {
+                if !directions.contains(&diagonal) {
+                    directions.push(diagonal);
+                }
+            }
         }
+        directions
     }
 
-    pub fn get_current_pos(&self) -> (u32, u32) {
-        self.current_pos
+    /// Check that one of the allowed directions can move within a walk area
+    /// of `width` x `height` cells
+    pub fn check_directions(&self, width: u32, height: u32) -> Result<(), String> {
+        let movable = self
+            .get_allowed_directions()
+            .iter()
+            .any(|&(dx, dy)| (dx != 0 && width > 1) || (dy != 0 && height > 1));
+        if !movable {
+            return Err(format!(
+                "none of the allowed_directions can move within the {width}x{height} walk bounds"
+            ));
+        }
+        Ok(())
     }
 
-    pub fn needs_update(&self) -> bool {
-        self.needs_update
+    /// Let the walker move up, down, left and right again, for when the
+    /// allowed directions turn out to be stuck
+    pub fn clear_allowed_directions(&mut self) {
+        self.walkers.allowed_directions.clear();
     }
 
-    pub fn get_width(&self) -> u32 {
-        self.grid_width
+    pub fn diagonals(&self) -> bool {
+        self.walkers.diagonals
     }
 
-    pub fn get_height(&self) -> u32 {
-        self.grid_height
+    pub fn get_stuck_behavior(&self) -> StuckBehavior {
+        StuckBehavior::from_name(&self.walkers.stuck_behavior).unwrap_or(StuckBehavior::Random)
     }
 
-    pub fn set_pos(&mut self, x: u32, y: u32) {
-        self.current_pos = (x, y);
+    pub fn jump_visits_path(&self) -> bool {
+        self.walkers.jump_visits_path
     }
 
-    pub fn clear_update_flag(&mut self) {
-        self.needs_update = false;
+    pub fn get_active_shape(&self) -> DotShape {
+        DotShape::from_name(&self.active_shape).unwrap_or(DotShape::Circle)
     }
 
-    /// Sets needs_update to true, regardless of the previous value
-    pub fn set_needs_update(&mut self) {
-        self.needs_update = true;
+    pub fn get_dot_shape(&self) -> DotShape {
+        DotShape::from_name(&self.dot_shape).unwrap_or(DotShape::Circle)
     }
-}
 
-/// Represents the grid of dots with visit counts
-pub struct Grid {
-    width: u32,
-    height: u32,
-    visits: Vec<u8>,
-}
+    pub fn get_vignette(&self) -> f32 {
+        self.vignette
+    }
 
-impl Grid {
-    pub fn new(width: u32, height: u32) -> Self {
-        let size = (width * height) as usize;
-        Grid {
-            width,
-            height,
-            visits: vec![0; size],
+    pub fn get_unvisited_color(&self) -> Option<u32> {
+        self.unvisited_color.map(Color::argb)
+    }
+
+    pub fn get_connection_style(&self) -> ConnectionStyle {
+        ConnectionStyle::from_name(&self.connection_style).unwrap_or(ConnectionStyle::Grid)
+    }
+
+    /// Steps to walk before the first frame, capped so startup stays fast
+    pub fn get_warmup_steps(&self) -> u32 {
+        self.warmup_steps.min(MAX_WARMUP_STEPS)
+    }
+
+    pub fn get_visit_increment(&self) -> u8 {
+        self.visit_increment
+    }
+
+    pub fn get_blend_mode(&self) -> BlendMode {
+        BlendMode::from_name(&self.blend_mode).unwrap_or(BlendMode::Over)
+    }
+
+    pub fn get_radius_overlap(&self) -> RadiusOverlap {
+        RadiusOverlap::from_name(&self.radius_overlap).unwrap_or(RadiusOverlap::Clip)
+    }
+
+    pub fn get_active_radius(&self) -> Option<u32> {
+        self.active_radius
+    }
+
+    pub fn get_spawn_prob(&self) -> f32 {
+        self.walkers.spawn_prob
+    }
+
+    pub fn get_retire_prob(&self) -> f32 {
+        self.walkers.retire_prob
+    }
+
+    pub fn get_levy_alpha(&self) -> Option<f32> {
+        self.walkers.levy_alpha
+    }
+
+    pub fn get_momentum(&self) -> f32 {
+        self.walkers.momentum
+    }
+
+    pub fn get_drift(&self) -> (f32, f32) {
+        self.walkers.drift
+    }
+
+    pub fn get_walker_count(&self) -> u32 {
+        self.walkers.walker_count
+    }
+
+    pub fn get_min_walkers(&self) -> u32 {
+        self.walkers.min_walkers
+    }
+
+    pub fn get_max_walkers(&self) -> u32 {
+        self.walkers.max_walkers
+    }
+
+    pub fn get_ghost_steps(&self) -> u32 {
+        self.ghost_steps
+    }
+
+    pub fn get_seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn get_theme(&self) -> Option<&str> {
+        self.theme.as_deref()
+    }
+
+    /// Take the colors of the built-in theme `name`, an unknown name keeps
+    /// the current colors
+    pub fn apply_theme(&mut self, name: &str) {
+        let Some(&(_, [bg, fg, active, heat])) = THEMES.iter().find(|(theme, _)| *theme == name)
+        else {
+            log::warn!("Unknown theme {name:?}, using the default colors");
+            return;
+        };
+        self.bg_color = Color::from_argb(bg);
+        self.fg_color = Color::from_argb(fg);
+        self.active_color = Color::from_argb(active);
+        self.heat_color = Color::from_argb(heat);
+    }
+
+    pub fn set_seed(&mut self, seed: Option<u64>) {
+        self.seed = seed;
+    }
+
+    pub fn get_snake_length(&self) -> Option<u32> {
+        self.snake_length
+    }
+
+    pub fn smooth_steps(&self) -> bool {
+        self.smooth_steps
+    }
+
+    pub fn get_max_surface_size(&self) -> u32 {
+        self.surface.max_surface_size
+    }
+
+    pub fn get_supersample(&self) -> u32 {
+        self.surface.supersample
+    }
+
+    pub fn watch_config(&self) -> bool {
+        self.daemon.watch_config
+    }
+
+    pub fn get_resize_policy(&self) -> ResizePolicy {
+        ResizePolicy::from_name(&self.resize_policy).unwrap_or(ResizePolicy::Preserve)
+    }
+
+    pub fn get_seed_pattern(&self) -> SeedPattern {
+        SeedPattern::from_name(&self.seed_pattern).unwrap_or(SeedPattern::None)
+    }
+
+    /// Make a relative `seed_pattern` image path relative to `dir` instead
+    pub fn resolve_paths(&mut self, dir: &std::path::Path) {
+        if let Some(SeedPattern::Image(path)) = SeedPattern::from_name(&self.seed_pattern)
+            && path.is_relative()
+        {
+            self.seed_pattern = dir.join(path).to_string_lossy().into_owned();
         }
     }
 
-    pub fn resize(&mut self, width: u32, height: u32) {
-        self.width = width;
-        self.height = height;
-        let size = (width * height) as usize;
-        self.visits.resize(size, 0);
-        self.visits.fill(0);
+    /// Time between two fades of the visit counts, `None` if cells never fade
+    pub fn get_decay_interval(&self) -> Option<std::time::Duration> {
+        if let Some(per_minute) = self.decay.decay_per_minute {
+            return Some(std::time::Duration::from_secs(60) / per_minute as u32);
+        }
+        (self.decay.decay_interval_secs > 0.0)
+            .then(|| std::time::Duration::from_secs_f32(self.decay.decay_interval_secs))
     }
 
-    pub fn visit(&mut self, x: u32, y: u32) {
-        if x < self.width && y < self.height {
-            let idx = (y * self.width + x) as usize;
-            self.visits[idx] = self.visits[idx].saturating_add(1);
+    pub fn get_pixel_format(&self) -> PixelFormat {
+        PixelFormat::from_name(&self.surface.pixel_format).unwrap_or(PixelFormat::Argb8888)
+    }
+
+    pub fn get_decay_mode(&self) -> DecayMode {
+        if self.decay.decay_per_minute.is_some() {
+            return DecayMode::Linear;
         }
+        DecayMode::from_name(&self.decay.decay_mode).unwrap_or(DecayMode::Linear)
     }
 
-    pub fn get_visits(&self, x: u32, y: u32) -> u8 {
-        if x < self.width && y < self.height {
-            let idx = (y * self.width + x) as usize;
-            self.visits[idx]
-        } else {
-            0
+    pub fn get_decay_amount(&self) -> u8 {
+        if self.decay.decay_per_minute.is_some() {
+            return 1;
         }
+        self.decay.decay_amount
     }
 
-    pub fn get_width(&self) -> u32 {
-        self.width
+    pub fn get_decay_factor(&self) -> f32 {
+        self.decay.decay_factor
     }
 
-    pub fn get_height(&self) -> u32 {
-        self.height
+    pub fn get_start(&self) -> StartPosition {
+        StartPosition::from_name(&self.start).unwrap_or(StartPosition::Center)
+    }
+
+    pub fn get_glow_radius(&self) -> u32 {
+        self.glow_radius
+    }
+
+    pub fn get_border_width(&self) -> u32 {
+        self.border_width
+    }
+
+    pub fn get_border_color(&self) -> u32 {
+        self.border_color.argb()
+    }
+
+    pub fn gradient_connections(&self) -> bool {
+        self.gradient_connections
+    }
+
+    pub fn ripple(&self) -> bool {
+        self.ripple
+    }
+
+    pub fn compass_colors(&self) -> bool {
+        self.compass.compass_colors
+    }
+
+    /// Color of a step going in `direction`, the vertical part wins for
+    /// diagonal steps
+    pub fn get_compass_color(&self, direction: (i64, i64)) -> u32 {
+        let color = match direction {
+            (_, dy) if dy < 0 => self.compass.compass_up_color,
+            (_, dy) if dy > 0 => self.compass.compass_down_color,
+            (dx, _) if dx < 0 => self.compass.compass_left_color,
+            _ => self.compass.compass_right_color,
+        };
+        color.argb()
+    }
+
+    pub fn get_dot_alpha(&self) -> u8 {
+        self.dot_alpha
+    }
+
+    pub fn get_boundary(&self) -> BoundaryMode {
+        BoundaryMode::from_name(&self.boundary).unwrap_or(BoundaryMode::Clamp)
+    }
+
+    pub fn get_preview_size(&self) -> (u32, u32) {
+        (self.preview.preview_width, self.preview.preview_height)
+    }
+
+    pub fn get_preview_steps(&self) -> u32 {
+        self.preview.preview_steps
+    }
+
+    pub fn persist_state(&self) -> bool {
+        self.daemon.persist_state
+    }
+
+    /// Where to write the walk statistics, `None` if nowhere
+    pub fn get_status_file(&self) -> Option<&str> {
+        self.daemon.status_file.as_deref()
+    }
+
+    /// How long to run before exiting, `None` if forever
+    pub fn get_run_duration(&self) -> Option<std::time::Duration> {
+        self.daemon
+            .run_duration_secs
+            .map(std::time::Duration::from_secs_f32)
+    }
+
+    /// How many walk steps to take before exiting, `None` if forever
+    pub fn get_max_steps(&self) -> Option<u64> {
+        self.daemon.max_steps
+    }
+
+    /// Where to save the last frame on exiting after a limit, `None` if nowhere
+    pub fn get_exit_screenshot(&self) -> Option<&str> {
+        self.daemon.exit_screenshot.as_deref()
+    }
+
+    /// How long a cell is remembered after its last visit, `None` if forever
+    pub fn get_trail_max_age(&self) -> Option<std::time::Duration> {
+        (self.decay.trail_max_age_secs > 0.0)
+            .then(|| std::time::Duration::from_secs_f32(self.decay.trail_max_age_secs))
     }
 }
 
-/// Stores application state
-pub struct App {
-    config: Config,
-    registry_state: RegistryState,
-    output_state: OutputState,
-    compositor_state: CompositorState,
-    shm_state: Shm,
-    layer_surface: Option<wlr_layer::LayerSurface>,
-    width: u32,
-    height: u32,
-    configured: bool,
-    pool: Option<wl_shm_pool::WlShmPool>,
-    grid: Grid,
-    current_pos: (u32, u32),
-    needs_redraw: bool,
-    file: std::fs::File,
-    mmap: Option<memmap2::MmapMut>,
-}
-
-impl App {
-    pub fn new(global_list: &globals::GlobalList, qh: &QueueHandle<Self>) -> Self {
-        let file = tempfile::tempfile().expect("Failed to create tempfile");
-        file.lock().expect("Failed to lock tempfile");
-
-        Self {
-            config: Config::default(),
-            registry_state: RegistryState::new(global_list),
-            output_state: OutputState::new(global_list, qh),
-            compositor_state: CompositorState::bind(global_list, qh)
-                .expect("Failed to bind compositor"),
-            shm_state: Shm::bind(global_list, qh).expect("Failed to bind shm"),
-            layer_surface: None,
-            width: 0,
-            height: 0,
-            configured: false,
-            pool: None,
-            grid: Grid::new(0, 0),
-            current_pos: (0, 0),
-            needs_redraw: false,
-            file: tempfile::tempfile().expect("Failed to create temp file"),
-            mmap: None,
-        }
-    }
-
-    pub fn create_surface(&mut self, qh: &QueueHandle<Self>, globals: &globals::GlobalList) {
-        let surface = self.compositor_state.create_surface(qh);
-        let layer_shell =
-            wlr_layer::LayerShell::bind(globals, qh).expect("Failed to bind layer shell");
-        let layer_surface = layer_shell.create_layer_surface(
-            qh,
-            surface,
-            wlr_layer::Layer::Background,
-            Some("walk_bg"),
-            None,
-        );
+/// A color in ARGB format, written as `"#rrggbb"` or `"#rrggbbaa"` in the
+/// config. The `#` is optional and the alpha defaults to `ff`. Older configs
+/// wrote colors as integers like `0xff1a1a1a`, those still work.
+#[derive(facet::Facet, Clone, Copy, PartialEq, Eq)]
+#[facet(proxy = ColorValue)]
+pub struct Color(u32);
+
+/// How a color can be written in the config
+#[derive(facet::Facet)]
+#[facet(untagged)]
+#[repr(u8)]
+pub enum ColorValue {
+    /// An integer in `0xaarrggbb` format
+    Argb(i64),
+    /// A hex string, see [`Color`]
+    Hex(String),
+}
 
-        layer_surface.set_anchor(wlr_layer::Anchor::all());
-        layer_surface.set_exclusive_zone(-1);
-        layer_surface.set_keyboard_interactivity(wlr_layer::KeyboardInteractivity::None);
-        layer_surface.commit();
+impl Color {
+    pub const fn from_argb(argb: u32) -> Self {
+        Self(argb)
+    }
 
-        self.layer_surface = Some(layer_surface);
+    pub fn argb(self) -> u32 {
+        self.0
     }
+}
 
-    pub fn set_config(&mut self, config: Config) {
-        self.config = config;
+impl std::str::FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        // from_str_radix alone would also take a sign
+        let value = Some(hex)
+            .filter(|hex| matches!(hex.len(), 6 | 8) && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| format!("invalid color {s:?}, expected \"#rrggbb\" or \"#rrggbbaa\""))?;
+
+        if hex.len() == 6 {
+            Ok(Self(0xff00_0000 | value))
+        } else {
+            // rrggbbaa to aarrggbb
+            Ok(Self(value.rotate_right(8)))
+        }
     }
+}
 
-    pub fn get_config(&self) -> &Config {
-        &self.config
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:06x}", self.0 & 0x00ff_ffff)?;
+        if self.0 >> 24 != 0xff {
+            write!(f, "{:02x}", self.0 >> 24)?;
+        }
+        Ok(())
     }
+}
 
-    pub fn is_configured(&self) -> bool {
-        self.configured
+impl std::fmt::Debug for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.to_string())
     }
+}
 
-    pub fn get_current_pos(&self) -> (u32, u32) {
-        self.current_pos
+/// Parses the colors of the config
+impl TryFrom<ColorValue> for Color {
+    type Error = String;
+
+    fn try_from(value: ColorValue) -> Result<Self, Self::Error> {
+        match value {
+            ColorValue::Argb(argb) => u32::try_from(argb)
+                .map(Self)
+                .map_err(|_| format!("invalid color {argb:#x}, expected at most 0xffffffff")),
+            ColorValue::Hex(hex) => hex.parse(),
+        }
     }
+}
 
-    pub fn set_pos(&mut self, x: u32, y: u32) {
-        self.current_pos = (x, y);
+impl From<&Color> for ColorValue {
+    fn from(color: &Color) -> Self {
+        ColorValue::Hex(color.to_string())
     }
+}
+
+/// Where the walker is placed when the walk starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartPosition {
+    /// The center of the walk bounds
+    Center,
+    /// Wherever the pointer is, the center if it can't be found
+    Cursor,
+}
+
+impl StartPosition {
+    /// Names accepted by the `start` config field
+    pub const NAMES: &[&str] = &["center", "cursor"];
 
-    pub fn set_needs_redraw(&mut self) {
-        self.needs_redraw = true;
+    /// Look up a start position by its config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "center" => Some(StartPosition::Center),
+            "cursor" => Some(StartPosition::Cursor),
+            _ => None,
+        }
     }
+}
+
+/// The layer of the shell the walk is drawn on, from the bottom up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerKind {
+    Background,
+    Bottom,
+    Top,
+    Overlay,
+}
 
-    pub fn needs_redraw(&self) -> bool {
-        self.needs_redraw
+impl LayerKind {
+    /// Names accepted by the `layer` config field
+    pub const NAMES: &[&str] = &["background", "bottom", "top", "overlay"];
+
+    /// Look up a layer by its config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "background" => Some(LayerKind::Background),
+            "bottom" => Some(LayerKind::Bottom),
+            "top" => Some(LayerKind::Top),
+            "overlay" => Some(LayerKind::Overlay),
+            _ => None,
+        }
     }
+}
+
+/// A screen edge a surface can be anchored to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
 
-    pub fn needs_no_redraw(&mut self) {
-        self.needs_redraw = false;
+impl Edge {
+    /// Names accepted by the `anchor` config field
+    pub const NAMES: &[&str] = &["top", "bottom", "left", "right"];
+
+    /// Look up an edge by its config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "top" => Some(Edge::Top),
+            "bottom" => Some(Edge::Bottom),
+            "left" => Some(Edge::Left),
+            "right" => Some(Edge::Right),
+            _ => None,
+        }
     }
+}
+
+/// What happens to the visits when the grid changes size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizePolicy {
+    /// Start over with an empty grid
+    Clear,
+    /// Keep the visits of cells that exist in both sizes
+    Preserve,
+    /// Stretch the old grid over the new one
+    Rescale,
+}
 
-    pub fn get_grid(&self) -> &Grid {
-        &self.grid
+impl ResizePolicy {
+    /// Names accepted by the `resize_policy` config field
+    pub const NAMES: &[&str] = &["clear", "preserve", "rescale"];
+
+    /// Look up a resize policy by its config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "clear" => Some(ResizePolicy::Clear),
+            "preserve" => Some(ResizePolicy::Preserve),
+            "rescale" => Some(ResizePolicy::Rescale),
+            _ => None,
+        }
     }
+}
 
-    /// Draw a new frame.
-    ///
-    /// # Safety
-    /// We use unsafe for mapping a file mutably into memory. The underlying file is
-    /// locked by default and there should be no program that randomly writes to any
-    /// tempfile. If you have a suggestion on how to handle this safer, feel free to
-    /// open an issue.
-    pub fn draw(&mut self, qh: &QueueHandle<Self>) {
-        if !self.configured || self.width == 0 || self.height == 0 {
-            return;
+/// How the visit counts fade over time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecayMode {
+    /// Take the same amount from every cell
+    Linear,
+    /// Take a share of every cell, so bright cells fade fastest
+    Exponential,
+}
+
+impl DecayMode {
+    /// Names accepted by the `decay_mode` config field
+    pub const NAMES: &[&str] = &["linear", "exponential"];
+
+    /// Look up a decay mode by its config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(DecayMode::Linear),
+            "exponential" => Some(DecayMode::Exponential),
+            _ => None,
         }
+    }
+}
 
-        let layer_surface = match &self.layer_surface {
-            Some(s) => s,
-            None => {
-                return;
+/// Patterns the grid can be seeded with before the walk starts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SeedPattern {
+    /// Start with an empty grid
+    None,
+    /// Every other cell, like a checkerboard
+    Checker,
+    /// The outermost ring of cells
+    Border,
+    /// A line from the top left to the bottom right corner
+    Diagonal,
+    /// A square spiral from the border towards the center
+    Spiral,
+    /// The luminance of a PNG image, scaled to the grid
+    Image(std::path::PathBuf),
+}
+
+impl SeedPattern {
+    /// Names accepted by the `seed_pattern` config field
+    pub const NAMES: &[&str] = &["none", "checker", "border", "diagonal", "spiral"];
+
+    /// Look up a pattern by its config name, names ending in `.png` are image paths
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(SeedPattern::None),
+            "checker" => Some(SeedPattern::Checker),
+            "border" => Some(SeedPattern::Border),
+            "diagonal" => Some(SeedPattern::Diagonal),
+            "spiral" => Some(SeedPattern::Spiral),
+            _ if name.to_ascii_lowercase().ends_with(".png") => {
+                Some(SeedPattern::Image(std::path::PathBuf::from(name)))
             }
-        };
+            _ => None,
+        }
+    }
+}
 
-        let width = self.width as i32;
-        let height = self.height as i32;
-        let stride = width * 4;
-        let size = stride * height;
+/// Decode the PNG at `path` into one luminance byte per pixel, transparent
+/// pixels count as black. Returns the luminance, width and height.
+fn read_luminance(path: &std::path::Path) -> Result<(Vec<u8>, u32, u32), String> {
+    let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let mut decoder = png::Decoder::new(std::io::BufReader::new(file));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let size = reader
+        .output_buffer_size()
+        .ok_or("image is too large".to_string())?;
+    let mut data = vec![0; size];
+    let info = reader.next_frame(&mut data).map_err(|e| e.to_string())?;
+    if info.width == 0 || info.height == 0 {
+        return Err("image is empty".to_string());
+    }
 
-        if self.mmap.is_none() {
-            self.mmap =
-                Some(unsafe { memmap2::MmapMut::map_mut(&self.file).expect("Failed to map file") });
-        }
+    let channels = info.color_type.samples();
+    let luminance = (0..info.height as usize)
+        .flat_map(|y| {
+            let row = &data[y * info.line_size..];
+            (0..info.width as usize).map(move |x| &row[x * channels..(x + 1) * channels])
+        })
+        .map(|pixel| {
+            let (luma, alpha) = match *pixel {
+                [gray] => (gray as u32, 255),
+                [gray, alpha] => (gray as u32, alpha as u32),
+                [r, g, b] => (
+                    (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000,
+                    255,
+                ),
+                [r, g, b, alpha, ..] => (
+                    (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000,
+                    alpha as u32,
+                ),
+                [] => (0, 0),
+            };
+            (luma * alpha / 255) as u8
+        })
+        .collect();
+    Ok((luminance, info.width, info.height))
+}
 
-        self.grid.visit(self.current_pos.0, self.current_pos.1);
+/// Represents the grid of dots with visit counts
+#[derive(Clone)]
+pub struct Grid {
+    width: u32,
+    height: u32,
+    visits: Vec<u8>,
+    last_visits: Vec<Option<std::time::Instant>>,
+    visit_increment: u8,
+}
 
-        crate::draw::draw_dot_grid(
-            self.mmap.as_mut().unwrap(),
-            self.width,
-            self.height,
-            self.config.clone(),
-            &self.grid,
-            self.current_pos,
-        );
+impl Grid {
+    pub fn new(width: u32, height: u32) -> Self {
+        let size = (width * height) as usize;
+        Grid {
+            width,
+            height,
+            visits: vec![0; size],
+            last_visits: vec![None; size],
+            visit_increment: 1,
+        }
+    }
 
-        if self.pool.is_none() {
-            self.pool = Some(
-                self.shm_state
-                    .wl_shm()
-                    .create_pool(self.file.as_fd(), size, qh, ()),
-            );
+    /// Create a grid from existing visit counts, `None` if they don't match the size.
+    ///
+    /// Visited cells count as last visited now.
+    pub fn from_visits(width: u32, height: u32, visits: Vec<u8>) -> Option<Self> {
+        if (width as usize).checked_mul(height as usize) != Some(visits.len()) {
+            return None;
         }
 
-        let buffer = self.pool.as_ref().unwrap().create_buffer(
-            0,
+        let now = std::time::Instant::now();
+        let last_visits = visits
+            .iter()
+            .map(|&count| (count > 0).then_some(now))
+            .collect();
+        Some(Grid {
             width,
             height,
-            stride,
-            wl_shm::Format::Argb8888,
-            qh,
-            (),
-        );
+            visits,
+            last_visits,
+            visit_increment: 1,
+        })
+    }
+
+    /// Change the grid size, `policy` decides what happens to the visits
+    pub fn resize(&mut self, width: u32, height: u32, policy: ResizePolicy) {
+        let size = (width * height) as usize;
+        let (old_width, old_height) = (self.width, self.height);
+        let source = |x: u32, y: u32| -> Option<usize> {
+            let (x, y) = match policy {
+                ResizePolicy::Clear => return None,
+                ResizePolicy::Preserve => (x, y),
+                ResizePolicy::Rescale => (x * old_width / width, y * old_height / height),
+            };
+            (x < old_width && y < old_height).then(|| (y * old_width + x) as usize)
+        };
+
+        let mut visits = vec![0; size];
+        let mut last_visits = vec![None; size];
+        for y in 0..height {
+            for x in 0..width {
+                if let Some(idx) = source(x, y) {
+                    let new_idx = (y * width + x) as usize;
+                    visits[new_idx] = self.visits[idx];
+                    last_visits[new_idx] = self.last_visits[idx];
+                }
+            }
+        }
 
-        let wl_surface = layer_surface.wl_surface();
-        wl_surface.attach(Some(&buffer), 0, 0);
-        wl_surface.damage_buffer(0, 0, width, height);
-        wl_surface.commit();
+        self.width = width;
+        self.height = height;
+        self.visits = visits;
+        self.last_visits = last_visits;
     }
-}
 
-impl CompositorHandler for App {
-    fn scale_factor_changed(
-        &mut self,
-        _conn: &Connection,
-        _qh: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
-        _new_factor: i32,
-    ) {
+    /// Mark every cell as unvisited
+    pub fn clear(&mut self) {
+        self.visits.fill(0);
+        self.last_visits.fill(None);
     }
 
-    fn transform_changed(
-        &mut self,
-        _conn: &Connection,
-        _qh: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
-        _new_transform: wl_output::Transform,
-    ) {
+    /// Set how much a single visit adds to the visit count of a cell
+    pub fn set_visit_increment(&mut self, increment: u8) {
+        self.visit_increment = increment;
     }
 
-    fn frame(
-        &mut self,
-        _conn: &Connection,
-        _qh: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
-        _time: u32,
-    ) {
+    pub fn visit(&mut self, x: u32, y: u32) {
+        if x < self.width && y < self.height {
+            let idx = (y * self.width + x) as usize;
+            self.visits[idx] = self.visits[idx].saturating_add(self.visit_increment);
+            self.last_visits[idx] = Some(std::time::Instant::now());
+        }
     }
 
-    fn surface_enter(
-        &mut self,
-        _conn: &Connection,
-        _qh: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
-        _output: &wl_output::WlOutput,
-    ) {
+    /// Reset every cell that wasn't visited within `max_age` back to unvisited
+    pub fn forget_older_than(&mut self, max_age: std::time::Duration) {
+        self.visits
+            .iter_mut()
+            .zip(self.last_visits.iter_mut())
+            .filter(|(_, last_visit)| last_visit.is_some_and(|time| time.elapsed() > max_age))
+            .for_each(|(visits, last_visit)| {
+                *visits = 0;
+                *last_visit = None;
+            });
     }
 
-    fn surface_leave(
-        &mut self,
-        _conn: &Connection,
-        _qh: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
-        _output: &wl_output::WlOutput,
-    ) {
+    /// Fade every cell once. Linear decay subtracts `amount`, exponential decay
+    /// multiplies by `factor` and rounds down, so cells always reach 0.
+    pub fn decay(&mut self, mode: DecayMode, amount: u8, factor: f32) {
+        self.visits
+            .iter_mut()
+            .zip(self.last_visits.iter_mut())
+            .filter(|(visits, _)| **visits > 0)
+            .for_each(|(visits, last_visit)| {
+                *visits = match mode {
+                    DecayMode::Linear => visits.saturating_sub(amount),
+                    DecayMode::Exponential => (*visits as f32 * factor) as u8,
+                };
+                if *visits == 0 {
+                    *last_visit = None;
+                }
+            });
+    }
+
+    /// Visit every cell that belongs to `pattern` once. An image sets the
+    /// visit counts to its luminance instead, so white cells start at 255.
+    pub fn seed_from_pattern(&mut self, pattern: SeedPattern) {
+        let (width, height) = (self.width, self.height);
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        match pattern {
+            SeedPattern::None => {}
+            SeedPattern::Checker => (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .filter(|(x, y)| (x + y) % 2 == 0)
+                .for_each(|(x, y)| self.visit(x, y)),
+            SeedPattern::Border => (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .filter(|&(x, y)| x == 0 || y == 0 || x == width - 1 || y == height - 1)
+                .for_each(|(x, y)| self.visit(x, y)),
+            SeedPattern::Diagonal => {
+                // Step along the longer side so the line has no gaps
+                let steps = width.max(height);
+                (0..steps).for_each(|i| {
+                    let x = (i as u64 * (width - 1) as u64 / (steps - 1).max(1) as u64) as u32;
+                    let y = (i as u64 * (height - 1) as u64 / (steps - 1).max(1) as u64) as u32;
+                    self.visit(x, y);
+                });
+            }
+            SeedPattern::Spiral => (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .filter(|&(x, y)| {
+                    // Every other ring is drawn, each one ends just below its top
+                    // left corner and steps right into the next one
+                    let ring = x.min(y).min(width - 1 - x).min(height - 1 - y);
+                    let link = x == ring && y == ring + 1;
+                    if ring % 2 == 0 { !link } else { link }
+                })
+                .for_each(|(x, y)| self.visit(x, y)),
+            SeedPattern::Image(path) => match read_luminance(&path) {
+                Ok((luminance, image_width, image_height)) => {
+                    let now = std::time::Instant::now();
+                    for y in 0..height {
+                        // Sample the image at the center of every cell
+                        let image_y = ((2 * y + 1) as u64 * image_height as u64
+                            / (2 * height) as u64) as usize;
+                        for x in 0..width {
+                            let image_x = ((2 * x + 1) as u64 * image_width as u64
+                                / (2 * width) as u64)
+                                as usize;
+                            let visits = luminance[image_y * image_width as usize + image_x];
+                            let idx = (y * width + x) as usize;
+                            self.visits[idx] = visits;
+                            self.last_visits[idx] = (visits > 0).then_some(now);
+                        }
+                    }
+                }
+                Err(e) => log::warn!("Failed to seed from {}: {e}", path.display()),
+            },
+        }
+    }
+
+    pub fn get_visits(&self, x: u32, y: u32) -> u8 {
+        if x < self.width && y < self.height {
+            let idx = (y * self.width + x) as usize;
+            self.visits[idx]
+        } else {
+            0
+        }
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
     }
-}
 
-impl OutputHandler for App {
-    fn output_state(&mut self) -> &mut OutputState {
-        &mut self.output_state
+    pub fn get_height(&self) -> u32 {
+        self.height
     }
 
-    fn new_output(
-        &mut self,
-        _conn: &Connection,
-        _qh: &QueueHandle<Self>,
-        _output: wl_output::WlOutput,
-    ) {
+    /// All visit counts, row by row
+    pub fn get_visits_slice(&self) -> &[u8] {
+        &self.visits
     }
 
-    fn update_output(
-        &mut self,
-        _conn: &Connection,
-        _qh: &QueueHandle<Self>,
-        _output: wl_output::WlOutput,
-    ) {
+    /// Sum of the visit counts of all cells
+    pub fn total_visits(&self) -> u64 {
+        self.visits.iter().map(|&count| count as u64).sum()
     }
 
-    fn output_destroyed(
-        &mut self,
-        _conn: &Connection,
-        _qh: &QueueHandle<Self>,
-        _output: wl_output::WlOutput,
-    ) {
+    /// Fraction of cells that were visited at least once
+    pub fn coverage(&self) -> f32 {
+        if self.visits.is_empty() {
+            return 0.0;
+        }
+        let visited = self.visits.iter().filter(|&&count| count > 0).count();
+        visited as f32 / self.visits.len() as f32
     }
 }
 
-impl LayerShellHandler for App {
-    fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _layer: &LayerSurface) {
-        println!("Layer surface closed");
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rows of the grid with `#` for visited and `.` for unvisited cells
+    fn visited_rows(grid: &Grid) -> Vec<String> {
+        (0..grid.get_height())
+            .map(|y| {
+                (0..grid.get_width())
+                    .map(|x| if grid.get_visits(x, y) > 0 { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
     }
 
-    fn configure(
-        &mut self,
-        _conn: &Connection,
-        qh: &QueueHandle<Self>,
-        _layer: &LayerSurface,
-        configure: LayerSurfaceConfigure,
-        _serial: u32,
-    ) {
-        self.width = configure.new_size.0;
-        self.height = configure.new_size.1;
+    /// A grid where the visit counts number the cells row by row, from 1
+    fn numbered(width: u32, height: u32) -> Grid {
+        let mut grid = Grid::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                grid.set_visit_increment((y * width + x + 1) as u8);
+                grid.visit(x, y);
+            }
+        }
+        grid
+    }
 
-        if let Err(e) = self.file.set_len((self.width * 4 * self.height) as u64) {
-            eprintln!("Failed to set tempfile length: {e}");
-        };
+    fn visit_counts(grid: &Grid) -> Vec<Vec<u8>> {
+        (0..grid.get_height())
+            .map(|y| {
+                (0..grid.get_width())
+                    .map(|x| grid.get_visits(x, y))
+                    .collect()
+            })
+            .collect()
+    }
 
-        if self.width == 0 || self.height == 0 {
-            self.width = 1920;
-            self.height = 1080;
+    fn seeded(width: u32, height: u32, name: &str) -> Vec<String> {
+        let mut grid = Grid::new(width, height);
+        grid.seed_from_pattern(SeedPattern::from_name(name).unwrap());
+        visited_rows(&grid)
+    }
+
+    #[test]
+    fn huge_durations_are_rejected() {
+        let mut config = Config::default();
+        config.decay.trail_max_age_secs = 1e30;
+        assert!(config.validate().is_err());
+        config.decay.trail_max_age_secs = MAX_SECS;
+        assert!(config.validate().is_ok());
+        assert!(config.get_trail_max_age().is_some());
+
+        config.decay.decay_interval_secs = 1e30;
+        assert!(config.validate().is_err());
+        config.decay.decay_interval_secs = MAX_SECS;
+        assert!(config.validate().is_ok());
+        assert!(config.get_decay_interval().is_some());
+
+        config.daemon.run_duration_secs = Some(1e30);
+        assert!(config.validate().is_err());
+        config.daemon.run_duration_secs = Some(MAX_SECS);
+        assert!(config.validate().is_ok());
+        assert!(config.get_run_duration().is_some());
+    }
+
+    #[test]
+    fn step_rate_is_bounded() {
+        let mut config = Config::default();
+        for walks_per_minute in [1e-40, f32::INFINITY, 1e6] {
+            config.walks_per_minute = walks_per_minute;
+            assert!(config.validate().is_err(), "{walks_per_minute}");
         }
+        config.walks_per_minute = MAX_STEPS_PER_MINUTE;
+        assert!(config.validate().is_ok());
+        assert!(!config.walk_interval().is_zero());
 
-        println!("Display size: {}x{}", self.width, self.height);
+        config.bpm = Some(1e30);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn set_walks_per_minute_replaces_bpm() {
+        let mut config = Config {
+            bpm: Some(120.0),
+            ..Default::default()
+        };
+        config.set_walks_per_minute(30.0);
+        assert!(config.validate().is_ok());
+        assert_eq!(config.steps_per_minute(), 30.0);
+    }
 
-        let grid_width = (self.width / self.config.pixels_per_point) + 1;
-        let grid_height = (self.height / self.config.pixels_per_point) + 1;
-        self.grid.resize(grid_width, grid_height);
-        self.current_pos = (grid_width / 2, grid_height / 2);
+    #[test]
+    fn exponential_decay_is_monotonic() {
+        let mut grid = numbered(15, 17);
+        let mut before = grid.get_visits_slice().to_vec();
+        while before.iter().any(|&visits| visits > 0) {
+            grid.decay(DecayMode::Exponential, 0, 0.9);
+            let after = grid.get_visits_slice().to_vec();
+            for (i, (&old, &new)) in before.iter().zip(&after).enumerate() {
+                assert!(new <= old && (old == 0 || new < old), "cell {i}");
+                // Cells that had more visits never end up with fewer
+                assert!(i == 0 || after[i - 1] <= new, "cell {i}");
+            }
+            before = after;
+        }
+    }
 
-        println!(
-            "Grid initialized: {}x{} (center: {:?})",
-            grid_width, grid_height, self.current_pos
+    #[test]
+    fn preserve_keeps_the_overlap() {
+        let mut grid = numbered(4, 4);
+        grid.resize(3, 5, ResizePolicy::Preserve);
+        assert_eq!(
+            visit_counts(&grid),
+            [[1, 2, 3], [5, 6, 7], [9, 10, 11], [13, 14, 15], [0, 0, 0]]
         );
+    }
 
-        self.configured = true;
+    #[test]
+    fn resize_policies() {
+        let mut grid = numbered(4, 4);
+        grid.resize(6, 6, ResizePolicy::Clear);
+        assert_eq!(visit_counts(&grid), [[0; 6]; 6]);
+
+        let mut grid = numbered(4, 4);
+        grid.resize(6, 6, ResizePolicy::Preserve);
+        assert_eq!(
+            visit_counts(&grid),
+            [
+                [1, 2, 3, 4, 0, 0],
+                [5, 6, 7, 8, 0, 0],
+                [9, 10, 11, 12, 0, 0],
+                [13, 14, 15, 16, 0, 0],
+                [0; 6],
+                [0; 6],
+            ]
+        );
 
-        self.draw(qh);
+        let mut grid = numbered(4, 4);
+        grid.resize(6, 6, ResizePolicy::Rescale);
+        assert_eq!(
+            visit_counts(&grid),
+            [
+                [1, 1, 2, 3, 3, 4],
+                [1, 1, 2, 3, 3, 4],
+                [5, 5, 6, 7, 7, 8],
+                [9, 9, 10, 11, 11, 12],
+                [9, 9, 10, 11, 11, 12],
+                [13, 13, 14, 15, 15, 16],
+            ]
+        );
     }
-}
 
-impl ShmHandler for App {
-    fn shm_state(&mut self) -> &mut Shm {
-        &mut self.shm_state
+    #[test]
+    fn colors_as_strings_and_integers() {
+        let config: Config = facet_toml::from_str(
+            "bg_color = 0xff1a1a1a\nfg_color = \"#606060\"\nactive_color = \"ff000080\"",
+        )
+        .unwrap();
+        assert_eq!(config.get_bg_color(), 0xff1a_1a1a);
+        assert_eq!(config.get_fg_color(), 0xff60_6060);
+        assert_eq!(config.get_active_color(), 0x80ff_0000);
+
+        for invalid in [
+            "bg_color = -1",
+            "bg_color = 0x1ffffffff",
+            "bg_color = \"#12345\"",
+        ] {
+            assert!(
+                facet_toml::from_str::<Config>(invalid).is_err(),
+                "{invalid}"
+            );
+        }
     }
-}
 
-impl ProvidesRegistryState for App {
-    fn registry(&mut self) -> &mut RegistryState {
-        &mut self.registry_state
+    #[test]
+    fn directions_must_fit_the_walk_bounds() {
+        let mut config = Config::default();
+        config.walkers.allowed_directions = vec!["up".to_string()];
+        config.walk_bounds = Some((0, 0, 10, 1));
+        assert!(config.validate().is_err());
+        config.walk_bounds = Some((0, 0, 1, 10));
+        assert!(config.validate().is_ok());
+        assert!(config.check_directions(10, 1).is_err());
+        config.walkers.diagonals = true;
+        assert!(config.check_directions(10, 1).is_ok());
     }
 
-    registry_handlers![OutputState];
+    #[test]
+    fn seed_patterns() {
+        assert_eq!(seeded(4, 3, "none"), ["....", "....", "...."]);
+        assert_eq!(seeded(4, 3, "checker"), ["#.#.", ".#.#", "#.#."]);
+        assert_eq!(seeded(4, 3, "border"), ["####", "#..#", "####"]);
+        assert_eq!(seeded(4, 3, "diagonal"), ["##..", "..#.", "...#"]);
+        assert_eq!(
+            seeded(5, 5, "spiral"),
+            ["#####", "....#", "###.#", "#...#", "#####"]
+        );
+    }
 }
-
-delegate_compositor!(App);
-delegate_output!(App);
-delegate_shm!(App);
-delegate_layer!(App);
-delegate_registry!(App);
-
-wayland_client::delegate_noop!(App: ignore wl_shm_pool::WlShmPool);
-wayland_client::delegate_noop!(App: ignore wl_buffer::WlBuffer);
@@ -1,22 +1,725 @@
 //! Moderately useful functions
 
-pub fn random_walk_step(x: u32, y: u32, width: u32, height: u32) -> (u32, u32) {
-    use std::collections::hash_map::RandomState;
-    use std::hash::{BuildHasher, Hash, Hasher};
-
-    let mut hasher = RandomState::new().build_hasher();
-    std::time::SystemTime::now().hash(&mut hasher);
-    x.hash(&mut hasher);
-    y.hash(&mut hasher);
-    let random = hasher.finish();
-
-    let direction = (random % 4) as u32;
-
-    match direction {
-        0 => (x, y.saturating_sub(1)),     // up
-        1 => ((x + 1).min(width - 1), y),  // right
-        2 => (x, (y + 1).min(height - 1)), // down
-        3 => (x.saturating_sub(1), y),     // left
-        _ => (x, y),
+use crate::types::{Config, Grid};
+
+/// Source of randomness for the walk, a xorshift64* generator.
+///
+/// Generators created with the same seed produce the same numbers, so a
+/// seeded walk can be repeated exactly.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Rng {
+    /// Create a generator seeded from system entropy
+    pub fn new() -> Self {
+        use std::hash::BuildHasher;
+
+        // A fresh `RandomState` is keyed with random numbers from the OS
+        let entropy = std::collections::hash_map::RandomState::new().hash_one(0u8);
+        Self::from_seed(entropy)
+    }
+
+    /// Create a generator that always produces the same numbers for `seed`
+    pub fn from_seed(seed: u64) -> Self {
+        // Spread the seed over all bits with splitmix64, xorshift gets stuck on 0
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        Rng {
+            state: if z == 0 { 0x9e37_79b9_7f4a_7c15 } else { z },
+        }
+    }
+
+    /// The state of the generator, [`Rng::from_state`] continues from it
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// Continue a generator from a state returned by [`Rng::state`]
+    pub fn from_state(state: u64) -> Self {
+        Rng {
+            state: if state == 0 {
+                0x9e37_79b9_7f4a_7c15
+            } else {
+                state
+            },
+        }
+    }
+
+    /// Restart from `new` if it differs from the seed `old` of the previous
+    /// config, otherwise keep going. Returns whether it restarted
+    pub fn reseed(&mut self, old: Option<u64>, new: Option<u64>) -> bool {
+        match new {
+            Some(seed) if old != new => {
+                *self = Self::from_seed(seed);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Get the next random number
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Get a random number in `0.0..1.0`
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// What happens when the walker reaches the edge of its bounds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoundaryMode {
+    /// Stay at the edge
+    Clamp,
+    /// Continue on the opposite edge
+    Wrap,
+    /// Bounce back from the edge
+    Reflect,
+}
+
+impl BoundaryMode {
+    /// Names accepted by the `boundary` config field
+    pub const NAMES: &[&str] = &["clamp", "wrap", "reflect"];
+
+    /// Look up a boundary mode by its config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "clamp" => Some(BoundaryMode::Clamp),
+            "wrap" => Some(BoundaryMode::Wrap),
+            "reflect" => Some(BoundaryMode::Reflect),
+            _ => None,
+        }
+    }
+
+    /// Move `pos` by `delta` along one axis, keeping it inside `min..min + size`
+    pub fn apply(self, pos: u32, delta: i64, min: u32, size: u32) -> u32 {
+        let size = size as i64;
+        let offset = pos as i64 - min as i64 + delta;
+        let offset = match self {
+            BoundaryMode::Clamp => offset.clamp(0, size - 1),
+            BoundaryMode::Wrap => offset.rem_euclid(size),
+            BoundaryMode::Reflect if size == 1 => 0,
+            BoundaryMode::Reflect => {
+                // Going back and forth repeats every 2 * (size - 1) cells
+                let period = 2 * (size - 1);
+                let offset = offset.rem_euclid(period);
+                if offset < size {
+                    offset
+                } else {
+                    period - offset
+                }
+            }
+        };
+        (min as i64 + offset) as u32
+    }
+}
+
+/// What a walker does when none of its preferred moves are possible
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StuckBehavior {
+    /// Take a uniform random step anyway
+    Random,
+    /// Jump to a random unvisited cell
+    Teleport,
+    /// Clear the grid and start again from the center
+    Reset,
+}
+
+impl StuckBehavior {
+    /// Names accepted by the `stuck_behavior` config field
+    pub const NAMES: &[&str] = &["random", "teleport", "reset"];
+
+    /// Look up a behavior by its config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "random" => Some(StuckBehavior::Random),
+            "teleport" => Some(StuckBehavior::Teleport),
+            "reset" => Some(StuckBehavior::Reset),
+            _ => None,
+        }
+    }
+}
+
+/// The algorithms the walker can use to pick its next position.
+///
+/// `drift`, `momentum` and `levy_alpha` aren't algorithms of their own, they
+/// shape the direction and length of a random step. They combine freely with
+/// each other and also steer a stuck self-avoiding walker, so they stay
+/// options that both algorithms read from the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalkAlgorithm {
+    /// Move to one of the four neighbors with equal probability, or jump
+    /// further with `levy_alpha`
+    Uniform,
+    /// Move to a random unvisited neighbor, the configured
+    /// [`StuckBehavior`] decides what happens when there is none
+    SelfAvoiding,
+}
+
+impl WalkAlgorithm {
+    /// Names accepted by the `walk_algorithm` config field
+    pub const NAMES: &[&str] = &["uniform", "self_avoiding"];
+
+    /// Look up an algorithm by its config name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "uniform" => Some(WalkAlgorithm::Uniform),
+            "self_avoiding" => Some(WalkAlgorithm::SelfAvoiding),
+            _ => None,
+        }
+    }
+
+    /// Take one step from `pos`, staying inside `bounds` given as
+    /// (x, y, width, height).
+    ///
+    /// `last_dir` is the direction of the walker's previous random move,
+    /// which `momentum` may repeat. It is updated with every random move.
+    /// The grid is only changed when a stuck walker resets it.
+    pub fn step(
+        &self,
+        pos: (u32, u32),
+        last_dir: &mut Option<(i64, i64)>,
+        bounds: (u32, u32, u32, u32),
+        grid: &mut Grid,
+        rng: &mut Rng,
+        config: &Config,
+    ) -> (u32, u32) {
+        let boundary = config.get_boundary();
+        let directions = config.get_allowed_directions();
+        match self {
+            WalkAlgorithm::Uniform => match config.get_levy_alpha() {
+                Some(alpha) => {
+                    levy_flight(rng, pos, last_dir, bounds, grid, config, &directions, alpha)
+                }
+                None => random_walk_step(rng, pos, last_dir, bounds, config, &directions),
+            },
+            WalkAlgorithm::SelfAvoiding => {
+                let (min_x, min_y, width, height) = bounds;
+                let unvisited: Vec<(u32, u32)> = directions
+                    .iter()
+                    .map(|&(dx, dy)| {
+                        (
+                            boundary.apply(pos.0, dx, min_x, width),
+                            boundary.apply(pos.1, dy, min_y, height),
+                        )
+                    })
+                    .filter(|&next| next != pos && grid.get_visits(next.0, next.1) == 0)
+                    .collect();
+
+                if !unvisited.is_empty() {
+                    return unvisited[(rng.next_u64() % unvisited.len() as u64) as usize];
+                }
+                stuck_step(rng, pos, last_dir, bounds, grid, config, &directions)
+            }
+        }
+    }
+}
+
+/// Offsets of the four neighbors: up, right, down, left
+pub const DIRECTIONS: [(i64, i64); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
+/// Offsets of the four diagonal neighbors: up left, up right, down left, down
+/// right
+pub const DIAGONALS: [(i64, i64); 4] = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+
+/// Names accepted by the `allowed_directions` config field and their offsets
+pub const DIRECTION_NAMES: &[(&str, (i64, i64))] = &[
+    ("up", (0, -1)),
+    ("right", (1, 0)),
+    ("down", (0, 1)),
+    ("left", (-1, 0)),
+    ("up_left", (-1, -1)),
+    ("up_right", (1, -1)),
+    ("down_left", (-1, 1)),
+    ("down_right", (1, 1)),
+];
+
+/// Look up the offset of a direction by its config name
+pub fn direction_from_name(name: &str) -> Option<(i64, i64)> {
+    DIRECTION_NAMES
+        .iter()
+        .find(|(direction, _)| *direction == name)
+        .map(|&(_, offset)| offset)
+}
+
+/// Direction of the step from `from` to `to` inside `bounds`, `None` if it
+/// wasn't a single step, like a teleport.
+///
+/// Steps across a wrapped edge count as going over the edge.
+pub fn step_direction(
+    from: (u32, u32),
+    to: (u32, u32),
+    bounds: (u32, u32, u32, u32),
+) -> Option<(i64, i64)> {
+    let axis = |from: u32, to: u32, size: u32| -> Option<i64> {
+        let delta = to as i64 - from as i64;
+        if delta.abs() <= 1 {
+            Some(delta)
+        } else if delta.abs() == size as i64 - 1 {
+            Some(-delta.signum())
+        } else {
+            None
+        }
+    };
+    let direction = (axis(from.0, to.0, bounds.2)?, axis(from.1, to.1, bounds.3)?);
+    (direction != (0, 0)).then_some(direction)
+}
+
+/// Cap a configured surface size to the largest known output, or to
+/// `ceiling` if no output is known or it is larger still.
+pub fn clamp_surface_size(
+    size: (u32, u32),
+    largest_output: Option<(u32, u32)>,
+    ceiling: u32,
+) -> (u32, u32) {
+    let (max_width, max_height) = largest_output.unwrap_or((ceiling, ceiling));
+    (
+        size.0.min(max_width.min(ceiling)),
+        size.1.min(max_height.min(ceiling)),
+    )
+}
+
+/// Start positions for `count` walkers: `center` first, then the cells in
+/// rings around it that are inside `bounds`.
+///
+/// If `bounds` has fewer cells than walkers, some walkers share a cell.
+pub fn start_positions(
+    center: (u32, u32),
+    count: u32,
+    bounds: (u32, u32, u32, u32),
+) -> Vec<(u32, u32)> {
+    let (min_x, min_y, width, height) = bounds;
+    let inside = |(x, y): (i64, i64)| {
+        (min_x as i64..min_x as i64 + width as i64).contains(&x)
+            && (min_y as i64..min_y as i64 + height as i64).contains(&y)
+    };
+
+    let mut positions = vec![center];
+    let max_ring = width.max(height) as i64;
+    let (center_x, center_y) = (center.0 as i64, center.1 as i64);
+    'rings: for ring in 1..=max_ring {
+        for dy in -ring..=ring {
+            for dx in -ring..=ring {
+                if dx.abs() != ring && dy.abs() != ring {
+                    continue;
+                }
+                if positions.len() >= count as usize {
+                    break 'rings;
+                }
+                let (x, y) = (center_x + dx, center_y + dy);
+                if inside((x, y)) {
+                    positions.push((x as u32, y as u32));
+                }
+            }
+        }
+    }
+
+    let cells = positions.len();
+    for i in cells..count as usize {
+        positions.push(positions[i % cells]);
+    }
+    positions
+}
+
+/// Spawn and retire walkers with the configured probabilities, keeping their
+/// number between `min_walkers` and `max_walkers`.
+///
+/// New walkers start on a random cell inside `bounds`, retiring removes the
+/// newest walker. The first walker is never retired.
+pub fn update_population(
+    walkers: &mut Vec<(u32, u32)>,
+    bounds: (u32, u32, u32, u32),
+    grid: &mut Grid,
+    rng: &mut Rng,
+    config: &Config,
+) {
+    let (min_x, min_y, width, height) = bounds;
+    if width == 0 || height == 0 {
+        return;
+    }
+    let min_walkers = config.get_min_walkers().max(1) as usize;
+    // Walkers the walk started with are never retired for being too many
+    let max_walkers = config
+        .get_max_walkers()
+        .max(config.get_walker_count())
+        .max(1) as usize;
+    let mut spawn = |walkers: &mut Vec<(u32, u32)>, rng: &mut Rng| {
+        let x = min_x + (rng.next_u64() % width as u64) as u32;
+        let y = min_y + (rng.next_u64() % height as u64) as u32;
+        grid.visit(x, y);
+        walkers.push((x, y));
+    };
+
+    if walkers.len() < max_walkers && rng.next_f32() < config.get_spawn_prob() {
+        spawn(walkers, rng);
+    }
+    if walkers.len() > min_walkers && rng.next_f32() < config.get_retire_prob() {
+        walkers.pop();
+    }
+
+    // The limits can change with a config reload
+    while walkers.len() < min_walkers {
+        spawn(walkers, rng);
+    }
+    walkers.truncate(max_walkers);
+}
+
+/// Pick the next position for a walker without any preferred move
+fn stuck_step(
+    rng: &mut Rng,
+    pos: (u32, u32),
+    last_dir: &mut Option<(i64, i64)>,
+    bounds: (u32, u32, u32, u32),
+    grid: &mut Grid,
+    config: &Config,
+    directions: &[(i64, i64)],
+) -> (u32, u32) {
+    let (min_x, min_y, width, height) = bounds;
+    match config.get_stuck_behavior() {
+        StuckBehavior::Random => {}
+        StuckBehavior::Teleport => {
+            let unvisited: Vec<(u32, u32)> = (min_y..min_y + height)
+                .flat_map(|y| (min_x..min_x + width).map(move |x| (x, y)))
+                .filter(|&(x, y)| grid.get_visits(x, y) == 0)
+                .collect();
+            if !unvisited.is_empty() {
+                let target = unvisited[(rng.next_u64() % unvisited.len() as u64) as usize];
+                if config.jump_visits_path() {
+                    visit_path(grid, pos, target);
+                }
+                return target;
+            }
+        }
+        StuckBehavior::Reset => {
+            grid.clear();
+            return (min_x + width / 2, min_y + height / 2);
+        }
+    }
+    random_walk_step(rng, pos, last_dir, bounds, config, directions)
+}
+
+/// Take one random step from `pos` in one of `directions`, see
+/// [`choose_direction`]
+fn random_walk_step(
+    rng: &mut Rng,
+    pos: (u32, u32),
+    last_dir: &mut Option<(i64, i64)>,
+    bounds: (u32, u32, u32, u32),
+    config: &Config,
+    directions: &[(i64, i64)],
+) -> (u32, u32) {
+    let (dx, dy) = choose_direction(rng, pos, last_dir, bounds, config, directions);
+
+    let (min_x, min_y, width, height) = bounds;
+    let boundary = config.get_boundary();
+    let next = (
+        boundary.apply(pos.0, dx, min_x, width),
+        boundary.apply(pos.1, dy, min_y, height),
+    );
+
+    // Bouncing off an edge turns the walker around on that axis
+    if boundary == BoundaryMode::Reflect {
+        let turn = |from: u32, to: u32, delta: i64| {
+            if from as i64 + delta == to as i64 {
+                delta
+            } else {
+                -delta
+            }
+        };
+        *last_dir = Some((turn(pos.0, next.0, dx), turn(pos.1, next.1, dy)));
+    }
+    next
+}
+
+/// Pick the direction of a random move. With probability `momentum` the
+/// walker keeps going in `last_dir`, unless that runs into a clamped edge.
+/// Otherwise one of `directions` is picked and becomes the new `last_dir`.
+fn choose_direction(
+    rng: &mut Rng,
+    pos: (u32, u32),
+    last_dir: &mut Option<(i64, i64)>,
+    bounds: (u32, u32, u32, u32),
+    config: &Config,
+    directions: &[(i64, i64)],
+) -> (i64, i64) {
+    let momentum = config.get_momentum();
+    if let Some((dx, dy)) = *last_dir
+        && momentum > 0.0
+        && rng.next_f32() < momentum
+    {
+        let (min_x, min_y, width, height) = bounds;
+        let inside = |pos: u32, delta: i64, min: u32, size: u32| {
+            (min as i64..min as i64 + size as i64).contains(&(pos as i64 + delta))
+        };
+        if config.get_boundary() != BoundaryMode::Clamp
+            || (inside(pos.0, dx, min_x, width) && inside(pos.1, dy, min_y, height))
+        {
+            return (dx, dy);
+        }
+    }
+
+    let direction = pick_direction(rng, directions, config.get_drift());
+    *last_dir = Some(direction);
+    direction
+}
+
+/// Jump a power-law distributed number of cells in one of `directions`,
+/// stopping early at a clamped edge.
+///
+/// With `jump_visits_path` every cell passed on the way is visited, the
+/// caller visits the cell the walker lands on.
+#[allow(clippy::too_many_arguments)]
+fn levy_flight(
+    rng: &mut Rng,
+    pos: (u32, u32),
+    last_dir: &mut Option<(i64, i64)>,
+    bounds: (u32, u32, u32, u32),
+    grid: &mut Grid,
+    config: &Config,
+    directions: &[(i64, i64)],
+    alpha: f32,
+) -> (u32, u32) {
+    let (min_x, min_y, width, height) = bounds;
+    let boundary = config.get_boundary();
+    let (dx, dy) = choose_direction(rng, pos, last_dir, bounds, config, directions);
+
+    // Pareto distributed with a minimum of 1, `1 - u` keeps the base above 0
+    let u = 1.0 - rng.next_f32();
+    let length = u.powf(-1.0 / alpha).min(width.max(height) as f32) as u32;
+
+    let mut current = pos;
+    for step in 1..=length.max(1) {
+        let next = (
+            boundary.apply(current.0, dx, min_x, width),
+            boundary.apply(current.1, dy, min_y, height),
+        );
+        if next == current {
+            break;
+        }
+        current = next;
+        if step < length && config.jump_visits_path() {
+            grid.visit(current.0, current.1);
+        }
+    }
+    current
+}
+
+/// Pick one of `directions` at random.
+///
+/// Each direction is weighted with `1 + drift · direction`, so a positive
+/// drift favors moving right and down. Without drift every direction is
+/// equally likely.
+fn pick_direction(rng: &mut Rng, directions: &[(i64, i64)], drift: (f32, f32)) -> (i64, i64) {
+    let uniform = |rng: &mut Rng| directions[(rng.next_u64() % directions.len() as u64) as usize];
+    if drift == (0.0, 0.0) {
+        return uniform(rng);
+    }
+
+    let weight =
+        |&(dx, dy): &(i64, i64)| (1.0 + drift.0 * dx as f32 + drift.1 * dy as f32).max(0.0);
+    let total: f32 = directions.iter().map(weight).sum();
+    if total <= 0.0 {
+        // Drift points away from every allowed direction
+        return uniform(rng);
+    }
+
+    let mut target = rng.next_f32() * total;
+    for direction in directions {
+        target -= weight(direction);
+        if target < 0.0 {
+            return *direction;
+        }
+    }
+    // Rounding can leave a tiny remainder after the last direction
+    *directions
+        .iter()
+        .rev()
+        .find(|direction| weight(direction) > 0.0)
+        .unwrap()
+}
+
+/// Visit every cell on the Bresenham line between `from` and `to`, leaving
+/// out both ends
+fn visit_path(grid: &mut Grid, from: (u32, u32), to: (u32, u32)) {
+    let (x1, y1) = (to.0 as i64, to.1 as i64);
+    let dx = (x1 - from.0 as i64).abs();
+    let dy = (y1 - from.1 as i64).abs();
+    let sx = if (from.0 as i64) < x1 { 1 } else { -1 };
+    let sy = if (from.1 as i64) < y1 { 1 } else { -1 };
+    let mut err = dx - dy;
+    let (mut x, mut y) = (from.0 as i64, from.1 as i64);
+
+    loop {
+        let e2 = 2 * err;
+        if e2 > -dy {
+            err -= dy;
+            x += sx;
+        }
+        if e2 < dx {
+            err += dx;
+            y += sy;
+        }
+
+        if x == x1 && y == y1 {
+            break;
+        }
+        grid.visit(x as u32, y as u32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(toml: &str) -> Config {
+        let config: Config = facet_toml::from_str(toml).unwrap();
+        config.validate().unwrap();
+        config
+    }
+
+    /// Let a self-avoiding walker in the middle of a 5x5 grid, with all its
+    /// neighbors visited, take one step
+    fn stuck(behavior: &str) -> ((u32, u32), Grid) {
+        let config = config(&format!(
+            "walk_algorithm = \"self_avoiding\"\nstuck_behavior = \"{behavior}\""
+        ));
+        let mut grid = Grid::new(5, 5);
+        for (x, y) in [(2, 2), (1, 2), (3, 2), (2, 1), (2, 3)] {
+            grid.visit(x, y);
+        }
+        let next = WalkAlgorithm::SelfAvoiding.step(
+            (2, 2),
+            &mut None,
+            (0, 0, 5, 5),
+            &mut grid,
+            &mut Rng::from_seed(1),
+            &config,
+        );
+        (next, grid)
+    }
+
+    /// Teleport a stuck walker from the corner of a 6x6 grid, where only the
+    /// diagonal is left unvisited
+    fn diagonal_jump(seed: u64, visit_path: bool) {
+        let config = config(&format!(
+            "walk_algorithm = \"self_avoiding\"\nstuck_behavior = \"teleport\"\njump_visits_path = {visit_path}"
+        ));
+        let mut grid = Grid::new(6, 6);
+        for y in 0..6 {
+            for x in 0..6 {
+                if x != y || x == 0 {
+                    grid.visit(x, y);
+                }
+            }
+        }
+        let next = WalkAlgorithm::SelfAvoiding.step(
+            (0, 0),
+            &mut None,
+            (0, 0, 6, 6),
+            &mut grid,
+            &mut Rng::from_seed(seed),
+            &config,
+        );
+
+        assert_eq!(next.0, next.1);
+        for k in 1..6 {
+            let visited = visit_path && k < next.0;
+            assert_eq!(grid.get_visits(k, k) > 0, visited, "cell {k},{k}");
+        }
+    }
+
+    #[test]
+    fn jumps_visit_the_path() {
+        for seed in 0..8 {
+            diagonal_jump(seed, true);
+            diagonal_jump(seed, false);
+        }
+
+        let mut grid = Grid::new(5, 3);
+        visit_path(&mut grid, (0, 0), (4, 2));
+        let visited: Vec<_> = (0..3)
+            .flat_map(|y| (0..5).map(move |x| (x, y)))
+            .filter(|&(x, y)| grid.get_visits(x, y) > 0)
+            .collect();
+        assert_eq!(visited, [(1, 0), (2, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn surface_size_is_clamped() {
+        let huge = (u32::MAX, u32::MAX);
+        let output = Some((2560, 1440));
+        assert_eq!(clamp_surface_size(huge, output, 16384), (2560, 1440));
+        assert_eq!(clamp_surface_size(huge, output, 1000), (1000, 1000));
+        assert_eq!(clamp_surface_size(huge, None, 16384), (16384, 16384));
+        assert_eq!(clamp_surface_size(huge, None, u32::MAX), huge);
+        assert_eq!(clamp_surface_size((0, 0), output, 16384), (0, 0));
+        assert_eq!(clamp_surface_size((0, 800), None, 0), (0, 0));
+    }
+
+    #[test]
+    fn horizontal_walks_keep_y() {
+        let horizontal =
+            "allowed_directions = [\"left\", \"right\"]\ndrift = [0.5, 0.9]\nmomentum = 0.5\n";
+        for extra in ["", "boundary = \"reflect\"", "levy_alpha = 1.5"] {
+            let config = config(&format!("{horizontal}{extra}"));
+            let mut rng = Rng::from_seed(3);
+            let mut grid = Grid::new(10, 10);
+            let mut last_dir = None;
+            let mut pos = (5, 5);
+            for _ in 0..1000 {
+                pos = WalkAlgorithm::Uniform.step(
+                    pos,
+                    &mut last_dir,
+                    (0, 0, 10, 10),
+                    &mut grid,
+                    &mut rng,
+                    &config,
+                );
+                assert_eq!(pos.1, 5, "{extra}");
+            }
+            for _ in 0..1000 {
+                assert_eq!(
+                    pick_direction(
+                        &mut rng,
+                        &config.get_allowed_directions(),
+                        config.get_drift()
+                    )
+                    .1,
+                    0
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn stuck_behaviors() {
+        let (next, grid) = stuck("random");
+        assert!([(1, 2), (3, 2), (2, 1), (2, 3)].contains(&next));
+        assert_eq!(grid.get_visits(2, 2), 1);
+
+        let (next, grid) = stuck("teleport");
+        assert_eq!(grid.get_visits(next.0, next.1), 0);
+        assert_eq!(grid.get_visits(2, 2), 1);
+
+        let (next, grid) = stuck("reset");
+        assert_eq!(next, (2, 2));
+        assert!(grid.get_visits_slice().iter().all(|&visits| visits == 0));
     }
 }
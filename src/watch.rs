@@ -0,0 +1,70 @@
+//! Watching the config file for changes
+
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use notify::{EventKind, RecursiveMode, Watcher};
+
+/// How long the config file has to stay untouched before it is reloaded
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Watches the config file and reports when it should be reloaded.
+///
+/// The parent directory is watched instead of the file itself, so editors that
+/// write a temporary file and rename it over the config don't break the watch.
+pub struct ConfigWatcher {
+    _watcher: notify::RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+    file_name: PathBuf,
+    changed_at: Option<Instant>,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: &Path) -> notify::Result<Self> {
+        let (tx, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+        Ok(ConfigWatcher {
+            _watcher: watcher,
+            events,
+            file_name: path.file_name().map(PathBuf::from).unwrap_or_default(),
+            changed_at: None,
+        })
+    }
+
+    /// Check for changes, returns true once the file settled after a change
+    pub fn poll(&mut self) -> bool {
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                Ok(event) if self.concerns_config(&event) => {
+                    self.changed_at = Some(Instant::now());
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!("Error while watching config file: {e}"),
+            }
+        }
+
+        match self.changed_at {
+            Some(changed_at) if changed_at.elapsed() >= DEBOUNCE => {
+                self.changed_at = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn concerns_config(&self, event: &notify::Event) -> bool {
+        !matches!(event.kind, EventKind::Access(_))
+            && event
+                .paths
+                .iter()
+                .any(|path| path.file_name() == Some(self.file_name.as_os_str()))
+    }
+}